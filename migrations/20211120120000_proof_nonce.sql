@@ -0,0 +1,9 @@
+-- used proof nonces (jti) of key signed register/login JWTs, for replay protection
+CREATE TABLE IF NOT EXISTS proof_nonce
+(
+    user_id BINARY(16) NOT NULL,
+    jti VARCHAR(64) COLLATE 'ascii_bin' NOT NULL,
+    expires DATETIME NOT NULL,
+    PRIMARY KEY (user_id,jti),
+    INDEX `expires` (`expires`)
+);
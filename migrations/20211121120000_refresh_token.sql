@@ -0,0 +1,13 @@
+-- refresh tokens for bearer token logins, only hashes are stored
+CREATE TABLE IF NOT EXISTS refresh_token
+(
+    token_hash BINARY(32) NOT NULL PRIMARY KEY,
+    user_id BINARY(16) NOT NULL,
+    created DATETIME NOT NULL,
+    expires DATETIME NOT NULL,
+    INDEX `expires` (`expires`),
+    CONSTRAINT `fk_user_id_refresh`
+        FOREIGN KEY (user_id) REFERENCES users (uuid)
+        ON DELETE CASCADE
+        ON UPDATE RESTRICT
+);
@@ -0,0 +1,24 @@
+-- server side login sessions, referenced by identity cookies and tokens
+CREATE TABLE IF NOT EXISTS user_session
+(
+    uuid BINARY(16) NOT NULL PRIMARY KEY,
+    user_id BINARY(16) NOT NULL,
+    device VARCHAR(127) COLLATE 'utf8mb4_general_ci',
+    created DATETIME NOT NULL,
+    last_used DATETIME NOT NULL,
+    method VARCHAR(20) COLLATE 'ascii_general_ci' NOT NULL,
+    INDEX (user_id),
+    INDEX `last_used` (`last_used`),
+    CONSTRAINT `fk_user_id_session`
+        FOREIGN KEY (user_id) REFERENCES users (uuid)
+        ON DELETE CASCADE
+        ON UPDATE RESTRICT
+);
+
+-- refresh tokens belong to a session now, older ones can't be mapped
+DELETE FROM refresh_token;
+ALTER TABLE refresh_token ADD COLUMN session BINARY(16) NOT NULL AFTER user_id;
+ALTER TABLE refresh_token ADD CONSTRAINT `fk_session_refresh`
+    FOREIGN KEY (session) REFERENCES user_session (uuid)
+    ON DELETE CASCADE
+    ON UPDATE RESTRICT;
@@ -0,0 +1,27 @@
+-- TOTP second factor for password logins
+CREATE TABLE IF NOT EXISTS user_totp
+(
+    user_id BINARY(16) NOT NULL PRIMARY KEY,
+    secret VARBINARY(64) NOT NULL,
+    -- set after enrollment got confirmed with a first code
+    confirmed BOOLEAN NOT NULL DEFAULT FALSE,
+    created DATETIME NOT NULL,
+    -- last accepted time step, codes can't be reused
+    last_step BIGINT,
+    CONSTRAINT `fk_user_id_totp`
+        FOREIGN KEY (user_id) REFERENCES users (uuid)
+        ON DELETE CASCADE
+        ON UPDATE RESTRICT
+);
+
+-- single use recovery codes, only hashes are stored
+CREATE TABLE IF NOT EXISTS totp_recovery
+(
+    user_id BINARY(16) NOT NULL,
+    code_hash BINARY(32) NOT NULL,
+    PRIMARY KEY (user_id, code_hash),
+    CONSTRAINT `fk_user_id_totp_recovery`
+        FOREIGN KEY (user_id) REFERENCES users (uuid)
+        ON DELETE CASCADE
+        ON UPDATE RESTRICT
+);
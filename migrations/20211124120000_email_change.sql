@@ -0,0 +1,13 @@
+-- pending email changes, the old login stays active until confirmed
+CREATE TABLE IF NOT EXISTS email_change
+(
+    user_id BINARY(16) NOT NULL PRIMARY KEY,
+    email VARCHAR(319) COLLATE 'utf8mb4_general_ci' NOT NULL,
+    token_hash BINARY(32) NOT NULL UNIQUE,
+    created DATETIME NOT NULL,
+    expires DATETIME NOT NULL,
+    CONSTRAINT `fk_user_id_email_change`
+        FOREIGN KEY (user_id) REFERENCES users (uuid)
+        ON DELETE CASCADE
+        ON UPDATE RESTRICT
+);
@@ -0,0 +1,28 @@
+-- external OpenID Connect subjects linked to users
+CREATE TABLE IF NOT EXISTS user_oidc
+(
+    provider VARCHAR(64) COLLATE 'ascii_bin' NOT NULL,
+    subject VARCHAR(255) COLLATE 'utf8mb4_bin' NOT NULL,
+    user_id BINARY(16) NOT NULL,
+    created DATETIME NOT NULL,
+    PRIMARY KEY (provider, subject),
+    INDEX (user_id),
+    CONSTRAINT `fk_user_id_oidc`
+        FOREIGN KEY (user_id) REFERENCES users (uuid)
+        ON DELETE CASCADE
+        ON UPDATE RESTRICT
+);
+
+-- started authorization code flows, looked up by the hashed state parameter
+CREATE TABLE IF NOT EXISTS oidc_flow
+(
+    state_hash BINARY(32) NOT NULL PRIMARY KEY,
+    provider VARCHAR(64) COLLATE 'ascii_bin' NOT NULL,
+    nonce VARCHAR(64) COLLATE 'ascii_bin' NOT NULL,
+    verifier VARCHAR(128) COLLATE 'ascii_bin' NOT NULL,
+    -- set when linking to an existing account
+    link_user BINARY(16),
+    device VARCHAR(127) COLLATE 'utf8mb4_general_ci',
+    expires DATETIME NOT NULL,
+    INDEX `expires` (`expires`)
+);
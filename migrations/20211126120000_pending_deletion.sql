@@ -0,0 +1,12 @@
+-- accounts scheduled for deletion, locked until restored by a login or finalized
+CREATE TABLE IF NOT EXISTS pending_deletion
+(
+    user_id BINARY(16) NOT NULL PRIMARY KEY,
+    requested DATETIME NOT NULL,
+    delete_at DATETIME NOT NULL,
+    INDEX `delete_at` (`delete_at`),
+    CONSTRAINT `fk_user_id_pending_deletion`
+        FOREIGN KEY (user_id) REFERENCES users (uuid)
+        ON DELETE CASCADE
+        ON UPDATE RESTRICT
+);
@@ -0,0 +1,16 @@
+-- security relevant events, kept after account deletion until the retention ends
+CREATE TABLE IF NOT EXISTS audit_log
+(
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    user_id BINARY(16) NOT NULL,
+    -- other user causing the event
+    actor BINARY(16),
+    event VARCHAR(40) COLLATE 'ascii_general_ci' NOT NULL,
+    list BINARY(16),
+    ip VARCHAR(45) COLLATE 'ascii_general_ci',
+    detail VARCHAR(255) COLLATE 'utf8mb4_general_ci',
+    created DATETIME NOT NULL,
+    INDEX `user_id` (`user_id`,`id`),
+    INDEX `actor` (`actor`,`id`),
+    INDEX `created` (`created`)
+);
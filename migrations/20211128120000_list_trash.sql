@@ -0,0 +1,3 @@
+-- deleted lists stay in the trash until purged, restorable by their owner
+ALTER TABLE lists ADD COLUMN deleted DATETIME NULL,
+    ADD INDEX `deleted` (`deleted`);
@@ -0,0 +1,34 @@
+-- previous values of lists and entries, written before every change
+CREATE TABLE IF NOT EXISTS revision
+(
+    id BIGINT UNSIGNED NOT NULL PRIMARY KEY AUTO_INCREMENT,
+    list BINARY(16) NOT NULL,
+    -- NULL for list revisions
+    `entry` BINARY(16),
+    -- author of the change, kept after account deletion
+    user BINARY(16) NOT NULL,
+    action VARCHAR(20) NOT NULL,
+    created DATETIME NOT NULL,
+    name VARCHAR(127),
+    name_a VARCHAR(127),
+    name_b VARCHAR(127),
+    tip VARCHAR(127),
+    INDEX `l_id` (`list`,`id`),
+    INDEX (`entry`),
+    CONSTRAINT `fk_list_id_revision`
+        FOREIGN KEY (list) REFERENCES lists (uuid)
+        ON DELETE CASCADE
+        ON UPDATE RESTRICT
+);
+
+CREATE TABLE IF NOT EXISTS revision_meaning
+(
+    revision BIGINT UNSIGNED NOT NULL,
+    value VARCHAR(120) NOT NULL,
+    is_a BOOLEAN NOT NULL DEFAULT FALSE,
+    INDEX (revision),
+    CONSTRAINT `fk_revision_id_meaning`
+        FOREIGN KEY (revision) REFERENCES revision (id)
+        ON DELETE CASCADE
+        ON UPDATE RESTRICT
+);
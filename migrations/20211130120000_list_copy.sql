@@ -0,0 +1,13 @@
+-- origin of copied lists and entries, to pull in later changes of the original
+ALTER TABLE lists ADD COLUMN source BINARY(16) NULL,
+    ADD INDEX `source` (`source`),
+    ADD CONSTRAINT `fk_list_source`
+        FOREIGN KEY (source) REFERENCES lists (uuid)
+        ON DELETE SET NULL
+        ON UPDATE RESTRICT;
+ALTER TABLE entries ADD COLUMN source BINARY(16) NULL,
+    ADD INDEX `source` (`source`),
+    ADD CONSTRAINT `fk_entry_source`
+        FOREIGN KEY (source) REFERENCES entries (uuid)
+        ON DELETE SET NULL
+        ON UPDATE RESTRICT;
@@ -0,0 +1,6 @@
+-- meanings compared for duplicates, lowercase with whitespace runs collapsed and trimmed,
+-- the only definition of normalization, see lists::dedup
+ALTER TABLE entry_meaning
+    ADD COLUMN normalized VARCHAR(120)
+        AS (TRIM(REGEXP_REPLACE(LOWER(value), '[[:space:]]+', ' '))) STORED,
+    ADD INDEX `normalized` (`normalized`,`is_a`);
@@ -0,0 +1,9 @@
+-- full text search over list names, tips and meanings, see lists::search
+--
+-- Words shorter than innodb_ft_min_token_size (default 3) and stopwords aren't indexed and
+-- can't be found. To index every word, set `innodb_ft_min_token_size = 1` and
+-- `innodb_ft_enable_stopword = OFF` in the server config before this migration runs.
+-- Indexes created earlier keep the old settings until they're dropped and added again.
+ALTER TABLE lists ADD FULLTEXT INDEX `ft_name` (`name`);
+ALTER TABLE entries ADD FULLTEXT INDEX `ft_tip` (`tip`);
+ALTER TABLE entry_meaning ADD FULLTEXT INDEX `ft_value` (`value`);
@@ -0,0 +1,5 @@
+-- creation time of entries, existing entries use their last change
+ALTER TABLE entries ADD COLUMN created DATETIME NOT NULL DEFAULT current_timestamp(),
+    ADD INDEX `l_created` (`list`,`created`),
+    ADD INDEX `l_changed` (`list`,`changed`);
+UPDATE entries SET created = changed;
@@ -0,0 +1,4 @@
+-- users who created and last changed an entry, kept after account deletion
+-- NULL for entries written before
+ALTER TABLE entries ADD COLUMN created_by BINARY(16) NULL,
+    ADD COLUMN changed_by BINARY(16) NULL;
@@ -0,0 +1,8 @@
+-- smallest meaning of each side as sort key of entries, kept up to date by lists::sorting
+ALTER TABLE entries ADD COLUMN sort_a VARCHAR(120) NOT NULL DEFAULT '',
+    ADD COLUMN sort_b VARCHAR(120) NOT NULL DEFAULT '',
+    ADD INDEX `l_sort_a` (`list`,`sort_a`,`uuid`),
+    ADD INDEX `l_sort_b` (`list`,`sort_b`,`uuid`);
+UPDATE entries e SET
+    sort_a = IFNULL((SELECT MIN(m.value) FROM entry_meaning m WHERE m.entry = e.uuid AND m.is_a),''),
+    sort_b = IFNULL((SELECT MIN(m.value) FROM entry_meaning m WHERE m.entry = e.uuid AND NOT m.is_a),'');
@@ -0,0 +1,2 @@
+-- revisions authored by a user, for the account export
+ALTER TABLE revision ADD INDEX `user` (`user`,`id`);
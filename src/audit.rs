@@ -0,0 +1,163 @@
+//! Audit log of security relevant events, visible to the affected users.
+//!
+//! Rows aren't bound to the users table, account deletions stay recorded until the retention ends.
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::TryStreamExt;
+use sqlx::{MySqlConnection, MySqlPool};
+use strum::AsRefStr;
+
+use crate::prelude::*;
+
+/// Interval of the retention cleanup
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Maximum length of details, see audit_log table
+const MAX_DETAIL_LENGTH: usize = 255;
+/// Maximum entries returned per activity request
+pub const MAX_ACTIVITY_LIMIT: u32 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum Event {
+    Login,
+    LoginFailed,
+    KeyRegistered,
+    PasswordAdded,
+    PasswordChanged,
+    EmailChanged,
+    TotpEnabled,
+    TotpDisabled,
+    SharecodeCreated,
+    SharecodeUsed,
+    SharePermissionsChanged,
+    ShareRemoved,
+    DeletionRequested,
+    DeletionCanceled,
+    AccountDeleted,
+}
+
+/// Audit log entry, written via [AuditLog::write]
+pub struct AuditLog<'a> {
+    user: &'a UserId,
+    event: Event,
+    actor: Option<&'a UserId>,
+    list: Option<&'a Uuid>,
+    ip: Option<&'a str>,
+    detail: Option<&'a str>,
+}
+
+impl<'a> AuditLog<'a> {
+    /// Event of `user`, the account whose activity log shows it
+    pub fn new(user: &'a UserId, event: Event) -> Self {
+        Self {
+            user,
+            event,
+            actor: None,
+            list: None,
+            ip: None,
+            detail: None,
+        }
+    }
+
+    /// Other user causing the event, it's also shown in their activity
+    pub fn actor(mut self, actor: &'a UserId) -> Self {
+        self.actor = Some(actor);
+        self
+    }
+
+    pub fn list(mut self, list: &'a Uuid) -> Self {
+        self.list = Some(list);
+        self
+    }
+
+    pub fn ip(mut self, ip: Option<&'a str>) -> Self {
+        self.ip = ip;
+        self
+    }
+
+    pub fn detail(mut self, detail: &'a str) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    pub async fn write(self, sql: &mut MySqlConnection) -> sqlx::Result<()> {
+        let detail: Option<String> = self
+            .detail
+            .map(|v| v.chars().take(MAX_DETAIL_LENGTH).collect());
+        let sql_insert = "INSERT INTO audit_log (user_id,actor,event,list,ip,detail,created)
+            VALUES(?,?,?,?,?,?,?)";
+        sqlx::query(sql_insert)
+            .bind(self.user.0)
+            .bind(self.actor.map(|v| v.0))
+            .bind(self.event.as_ref())
+            .bind(self.list)
+            .bind(self.ip)
+            .bind(detail)
+            .bind(Utc::now().naive_utc())
+            .execute(sql)
+            .await?;
+        trace!(user = %self.user, event = self.event.as_ref(), "audit log");
+        Ok(())
+    }
+}
+
+/// Audit log entry as shown to users
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Activity {
+    pub id: u64,
+    pub user_id: Uuid,
+    pub actor: Option<Uuid>,
+    pub event: String,
+    pub list: Option<Uuid>,
+    pub ip: Option<String>,
+    pub detail: Option<String>,
+    pub created: Timestamp,
+}
+
+/// Entries of or caused by the user, newest first. `before` continues after the given id.
+pub async fn activity(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    before: Option<u64>,
+    limit: u32,
+) -> sqlx::Result<Vec<Activity>> {
+    let sql_fetch = "SELECT id,user_id,actor,event,list,ip,detail,created FROM audit_log
+        WHERE (user_id = ? OR actor = ?) AND id < ? ORDER BY id DESC LIMIT ?";
+    sqlx::query_as::<_, Activity>(sql_fetch)
+        .bind(user.0)
+        .bind(user.0)
+        .bind(before.unwrap_or(u64::MAX))
+        .bind(limit.min(MAX_ACTIVITY_LIMIT))
+        .fetch(sql)
+        .try_collect()
+        .await
+}
+
+/// Remove entries older than `retention` days, returns the amount removed
+pub async fn purge(sql: &mut MySqlConnection, retention: u32) -> sqlx::Result<u64> {
+    let before = Utc::now().naive_utc() - chrono::Duration::days(retention.into());
+    let res = sqlx::query("DELETE FROM audit_log WHERE created < ?")
+        .bind(before)
+        .execute(sql)
+        .await?;
+    trace!(affected = res.rows_affected(), "purged audit log");
+    Ok(res.rows_affected())
+}
+
+/// Run [purge] periodically on the current runtime
+pub fn spawn_purge(pool: MySqlPool, retention: u32) {
+    actix_rt::spawn(async move {
+        let mut timer = actix_rt::time::interval(PURGE_INTERVAL);
+        loop {
+            timer.tick().await;
+            let res = match pool.acquire().await {
+                Ok(mut conn) => purge(&mut conn, retention).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                warn!(?e, "purging audit log failed");
+            }
+        }
+    });
+}
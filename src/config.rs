@@ -1,6 +1,11 @@
+use std::collections::HashMap;
+
 use config::{Config, ConfigError, Environment, File};
+use jsonwebtoken::Algorithm;
 use serde::Deserialize;
 
+use crate::users::user::KeyType;
+
 #[derive(Debug, Deserialize)]
 pub struct Database {
     pub host: String,
@@ -11,11 +16,224 @@ pub struct Database {
     pub max_conn: u32,
 }
 
+/// Allowed JWT algorithms for key based register/login proofs, per key type
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct KeyAlgorithms {
+    pub rsa_pem: Vec<Algorithm>,
+    pub ec_pem: Vec<Algorithm>,
+    pub ed_pem: Vec<Algorithm>,
+}
+
+impl KeyAlgorithms {
+    /// Allowed algorithms for the given key type
+    pub fn for_key(&self, k_type: &KeyType) -> &[Algorithm] {
+        match k_type {
+            KeyType::RSA_PEM => &self.rsa_pem,
+            KeyType::EC_PEM => &self.ec_pem,
+            KeyType::ED_PEM => &self.ed_pem,
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        for k_type in [KeyType::RSA_PEM, KeyType::EC_PEM, KeyType::ED_PEM] {
+            let algorithms = self.for_key(&k_type);
+            if algorithms.is_empty() {
+                return Err(ConfigError::Message(format!(
+                    "no algorithms allowed for key type {:?}",
+                    k_type
+                )));
+            }
+            if let Some(alg) = algorithms.iter().find(|a| !k_type.supports(**a)) {
+                return Err(ConfigError::Message(format!(
+                    "algorithm {:?} not usable with key type {:?}",
+                    alg, k_type
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for KeyAlgorithms {
+    fn default() -> Self {
+        Self {
+            rsa_pem: vec![Algorithm::RS256, Algorithm::RS384, Algorithm::RS512],
+            ec_pem: vec![Algorithm::ES256, Algorithm::ES384],
+            ed_pem: vec![Algorithm::EdDSA],
+        }
+    }
+}
+
+/// Throttling of failed password logins, delays in seconds
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoginLimit {
+    pub enabled: bool,
+    /// Failed attempts per account before throttling starts
+    pub free_attempts: u32,
+    /// Failed attempts per client IP before throttling starts
+    pub free_attempts_ip: u32,
+    /// Delay after the first throttled attempt, doubled on each further failure
+    pub base_delay: u64,
+    pub max_delay: u64,
+    /// Forget failures after this time without a new one
+    pub reset_after: u64,
+}
+
+impl Default for LoginLimit {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            free_attempts: 5,
+            free_attempts_ip: 20,
+            base_delay: 1,
+            max_delay: 15 * 60,
+            reset_after: 60 * 60,
+        }
+    }
+}
+
+/// Argon2id cost for password hashes. Raising it upgrades existing hashes on their next login.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PasswordHashing {
+    /// Memory in KiB
+    pub memory: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl PasswordHashing {
+    pub fn params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(self.memory, self.iterations, self.parallelism, None)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.params()
+            .map(|_| ())
+            .map_err(|e| ConfigError::Message(format!("invalid password hashing params: {}", e)))
+    }
+}
+
+impl Default for PasswordHashing {
+    fn default() -> Self {
+        Self {
+            memory: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Grace period of account deletions, times in seconds
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AccountDeletion {
+    /// Time the account stays restorable via login, 0 deletes immediately
+    pub grace_period: u64,
+    /// Interval of the background job finalizing due deletions
+    pub finalize_interval: u64,
+}
+
+impl Default for AccountDeletion {
+    fn default() -> Self {
+        Self {
+            grace_period: 14 * 24 * 60 * 60,
+            finalize_interval: 60 * 60,
+        }
+    }
+}
+
+/// Audit log settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Audit {
+    /// Days entries are kept
+    pub retention: u32,
+}
+
+impl Default for Audit {
+    fn default() -> Self {
+        Self { retention: 90 }
+    }
+}
+
+/// Trash of deleted lists
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Trash {
+    /// Days deleted lists stay restorable
+    pub retention: u32,
+}
+
+impl Default for Trash {
+    fn default() -> Self {
+        Self { retention: 30 }
+    }
+}
+
+/// OpenID Connect provider for login
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcProvider {
+    /// Issuer URL, used for discovery
+    pub issuer: String,
+    pub client_id: String,
+    /// Not required for public clients
+    pub client_secret: Option<String>,
+    /// Callback route of this server, `/api/v1/account/oidc/{provider}/callback`
+    pub redirect_uri: String,
+    #[serde(default = "OidcProvider::default_scopes")]
+    pub scopes: Vec<String>,
+}
+
+impl OidcProvider {
+    fn default_scopes() -> Vec<String> {
+        vec![
+            "openid".to_owned(),
+            "profile".to_owned(),
+            "email".to_owned(),
+        ]
+    }
+}
+
+/// SMTP server for account mails, features requiring mails are unavailable without it
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mail {
+    pub host: String,
+    /// Defaults to 465 with TLS, 587 with STARTTLS
+    pub port: Option<u16>,
+    /// Implicit TLS instead of STARTTLS
+    #[serde(default)]
+    pub tls: bool,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Sender address, `Name <address>` or the plain address
+    pub from: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Settings {
     pub database: Database,
     pub listen_ip: String,
     pub listen_port: u16,
+    #[serde(default)]
+    pub key_algorithms: KeyAlgorithms,
+    #[serde(default)]
+    pub login_limit: LoginLimit,
+    #[serde(default)]
+    pub password_hashing: PasswordHashing,
+    #[serde(default)]
+    pub account_deletion: AccountDeletion,
+    #[serde(default)]
+    pub audit: Audit,
+    #[serde(default)]
+    pub trash: Trash,
+    /// OIDC login providers by name
+    #[serde(default)]
+    pub oidc: HashMap<String, OidcProvider>,
+    #[serde(default)]
+    pub mail: Option<Mail>,
 }
 
 impl Settings {
@@ -32,6 +250,14 @@ impl Settings {
         // Eg.. `APP_DEBUG=1 ./target/app` would set the `debug` key
         s.merge(Environment::with_prefix("app"))?;
         // You can deserialize (and thus freeze) the entire configuration as
-        s.try_into()
+        let settings: Self = s.try_into()?;
+        settings.key_algorithms.validate()?;
+        settings.password_hashing.validate()?;
+        if settings.account_deletion.finalize_interval == 0 {
+            return Err(ConfigError::Message(
+                "account_deletion.finalize_interval must be positive".to_owned(),
+            ));
+        }
+        Ok(settings)
     }
 }
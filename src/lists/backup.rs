@@ -0,0 +1,327 @@
+//! Reading of VocableTrainer Android database backups, inserted by [super::dao::import_backup].
+//!
+//! Expected tables of the app database:
+//! - `list` (`list`, `list_name`, `name_a`, `name_b`, `created`, `changed`, optional `uuid`)
+//! - `vocables` (`voc`, `list`, `tip`, `created`, `changed`, optional `uuid`)
+//! - `meaning_a`, `meaning_b` (`voc`, `meaning`)
+//! - optional `category` (`category`, `category_name`, optional `uuid`) and `list_categories` (`list`, `category`)
+//!
+//! Times are milliseconds since the epoch. Rows without UUID get one derived from the
+//! user and their local id, so importing the same backup twice doesn't duplicate data.
+//!
+//! The file is untrusted: views and triggers are rejected and the row count is limited
+//! before anything is read.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use actix_web::web;
+use chrono::{NaiveDateTime, Utc};
+use futures::TryStreamExt;
+use sha1::{Digest, Sha1};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{ConnectOptions, Connection};
+
+use super::models::*;
+use super::*;
+
+/// Maximum size of an uploaded backup
+pub const MAX_BACKUP_SIZE: usize = 32 * 1024 * 1024;
+/// Maximum rows of all read tables together
+pub const MAX_BACKUP_ROWS: i64 = 100_000;
+/// Tables every backup has
+const REQUIRED_TABLES: [&str; 4] = ["list", "vocables", "meaning_a", "meaning_b"];
+/// Tables of newer app versions
+const OPTIONAL_TABLES: [&str; 2] = ["category", "list_categories"];
+
+pub struct Backup {
+    pub lists: Vec<BackupList>,
+    pub categories: Vec<BackupCategory>,
+}
+
+pub struct BackupList {
+    pub uuid: Uuid,
+    pub name: String,
+    pub name_a: String,
+    pub name_b: String,
+    pub created: Timestamp,
+    pub changed: Timestamp,
+    pub entries: Vec<BackupEntry>,
+    /// UUIDs of its categories
+    pub categories: Vec<Uuid>,
+}
+
+pub struct BackupEntry {
+    /// Local id in the app
+    pub id: i64,
+    pub uuid: Uuid,
+    pub tip: String,
+    pub created: Timestamp,
+    pub changed: Timestamp,
+    pub meanings: Vec<EntryMeaning>,
+}
+
+pub struct BackupCategory {
+    pub uuid: Uuid,
+    pub name: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct ListRow {
+    list: i64,
+    list_name: String,
+    name_a: String,
+    name_b: String,
+    created: Option<i64>,
+    changed: Option<i64>,
+    uuid: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct EntryRow {
+    voc: i64,
+    list: i64,
+    tip: Option<String>,
+    created: Option<i64>,
+    changed: Option<i64>,
+    uuid: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct CategoryRow {
+    category: i64,
+    category_name: String,
+    uuid: Option<String>,
+}
+
+/// Read the request body, up to [MAX_BACKUP_SIZE]
+pub async fn read_body(mut body: web::Payload) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    while let Some(chunk) = body.try_next().await.map_err(|e| {
+        debug!(?e, "reading backup upload failed");
+        ListError::ValidationError("body")
+    })? {
+        if data.len() + chunk.len() > MAX_BACKUP_SIZE {
+            return Err(ListError::ValidationError("backup size"));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+/// Parse the backup of `user`. SQLite requires a file, so it's stored temporarily.
+pub async fn read_backup(data: Vec<u8>, user: &UserId) -> Result<Backup> {
+    let path = std::env::temp_dir().join(format!("vta-backup-{}.db", Uuid::new_v4()));
+    let write_path = path.clone();
+    web::block(move || std::fs::write(write_path, data))
+        .await
+        .context("writing backup file")?
+        .context("writing backup file")?;
+
+    let res = read_backup_file(&path, user).await;
+    if let Err(e) = std::fs::remove_file(&path) {
+        warn!(?e, ?path, "removing backup file failed");
+    }
+    res
+}
+
+async fn read_backup_file(path: &Path, user: &UserId) -> Result<Backup> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(PathBuf::from(path))
+        .read_only(true)
+        .connect()
+        .await
+        .map_err(invalid_backup)?;
+    let res = match check_backup(&mut conn).await {
+        Ok(()) => read_tables(&mut conn, user).await.map_err(invalid_backup),
+        Err(e) => Err(e),
+    };
+    conn.close().await.map_err(invalid_backup)?;
+    res
+}
+
+/// Verify the file before reading: only plain tables and a bounded amount of rows
+async fn check_backup(conn: &mut SqliteConnection) -> Result<()> {
+    // no functions with side effects in schema defined by the file
+    sqlx::query("PRAGMA trusted_schema = OFF")
+        .execute(&mut *conn)
+        .await
+        .map_err(invalid_backup)?;
+    let check: Vec<String> = sqlx::query_scalar("PRAGMA quick_check")
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(invalid_backup)?;
+    if check.len() != 1 || check[0] != "ok" {
+        debug!(?check, "corrupt backup");
+        return Err(ListError::ValidationError("backup"));
+    }
+    let active: Option<i32> =
+        sqlx::query_scalar("SELECT 1 FROM sqlite_master WHERE type IN ('view','trigger')")
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(invalid_backup)?;
+    if active.is_some() {
+        return Err(ListError::ValidationError("backup"));
+    }
+
+    let mut rows = 0;
+    for table in REQUIRED_TABLES.iter().chain(OPTIONAL_TABLES.iter()) {
+        if !has_table(conn, table).await.map_err(invalid_backup)? {
+            if REQUIRED_TABLES.contains(table) {
+                return Err(ListError::ValidationError("backup"));
+            }
+            continue;
+        }
+        let sql_count = format!("SELECT COUNT(*) FROM {}", table);
+        let count: i64 = sqlx::query_scalar(&sql_count)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(invalid_backup)?;
+        rows += count;
+        if rows > MAX_BACKUP_ROWS {
+            return Err(ListError::ValidationError("backup rows"));
+        }
+    }
+    Ok(())
+}
+
+fn invalid_backup(e: sqlx::Error) -> ListError {
+    debug!(?e, "invalid backup");
+    ListError::ValidationError("backup")
+}
+
+/// Read all data, only after [check_backup]
+async fn read_tables(conn: &mut SqliteConnection, user: &UserId) -> sqlx::Result<Backup> {
+    let sql_lists = format!(
+        "SELECT list,list_name,name_a,name_b,created,changed,{} AS uuid FROM list",
+        uuid_column(conn, "list").await?
+    );
+    let list_rows: Vec<ListRow> = sqlx::query_as(&sql_lists).fetch_all(&mut *conn).await?;
+
+    let mut meanings: HashMap<i64, Vec<EntryMeaning>> = HashMap::new();
+    for &(table, is_a) in [("meaning_a", true), ("meaning_b", false)].iter() {
+        let sql_meanings = format!("SELECT voc,meaning FROM {}", table);
+        let mut rows = sqlx::query_as::<_, (i64, String)>(&sql_meanings).fetch(&mut *conn);
+        while let Some((voc, value)) = rows.try_next().await? {
+            meanings
+                .entry(voc)
+                .or_default()
+                .push(EntryMeaning { value, is_a });
+        }
+    }
+
+    let sql_entries = format!(
+        "SELECT voc,list,tip,created,changed,{} AS uuid FROM vocables",
+        uuid_column(conn, "vocables").await?
+    );
+    let mut entries: HashMap<i64, Vec<BackupEntry>> = HashMap::new();
+    let mut rows = sqlx::query_as::<_, EntryRow>(&sql_entries).fetch(&mut *conn);
+    while let Some(row) = rows.try_next().await? {
+        entries.entry(row.list).or_default().push(BackupEntry {
+            id: row.voc,
+            uuid: row_uuid(row.uuid.as_deref(), user, "entry", row.voc),
+            tip: row.tip.unwrap_or_default(),
+            created: timestamp(row.created.or(row.changed)),
+            changed: timestamp(row.changed),
+            meanings: meanings.remove(&row.voc).unwrap_or_default(),
+        });
+    }
+    drop(rows);
+
+    let mut categories = Vec::new();
+    let mut list_categories: HashMap<i64, Vec<Uuid>> = HashMap::new();
+    if has_table(conn, "category").await? {
+        let sql_categories = format!(
+            "SELECT category,category_name,{} AS uuid FROM category",
+            uuid_column(conn, "category").await?
+        );
+        let rows: Vec<CategoryRow> = sqlx::query_as(&sql_categories)
+            .fetch_all(&mut *conn)
+            .await?;
+        let mut category_uuids = HashMap::with_capacity(rows.len());
+        for row in rows {
+            let uuid = row_uuid(row.uuid.as_deref(), user, "category", row.category);
+            category_uuids.insert(row.category, uuid);
+            categories.push(BackupCategory {
+                uuid,
+                name: row.category_name,
+            });
+        }
+        if has_table(conn, "list_categories").await? {
+            let links: Vec<(i64, i64)> =
+                sqlx::query_as("SELECT list,category FROM list_categories")
+                    .fetch_all(&mut *conn)
+                    .await?;
+            for (list, category) in links {
+                if let Some(uuid) = category_uuids.get(&category) {
+                    list_categories.entry(list).or_default().push(*uuid);
+                }
+            }
+        }
+    }
+
+    let lists = list_rows
+        .into_iter()
+        .map(|row| BackupList {
+            uuid: row_uuid(row.uuid.as_deref(), user, "list", row.list),
+            name: row.list_name,
+            name_a: row.name_a,
+            name_b: row.name_b,
+            created: timestamp(row.created),
+            changed: timestamp(row.changed),
+            entries: entries.remove(&row.list).unwrap_or_default(),
+            categories: list_categories.remove(&row.list).unwrap_or_default(),
+        })
+        .collect();
+    Ok(Backup { lists, categories })
+}
+
+async fn has_table(conn: &mut SqliteConnection, table: &str) -> sqlx::Result<bool> {
+    let res: Option<i32> =
+        sqlx::query_scalar("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(table)
+            .fetch_optional(conn)
+            .await?;
+    Ok(res.is_some())
+}
+
+/// `uuid` if the table has that column, older app versions don't
+async fn uuid_column(conn: &mut SqliteConnection, table: &str) -> sqlx::Result<&'static str> {
+    let res: Option<i32> =
+        sqlx::query_scalar("SELECT 1 FROM pragma_table_info(?) WHERE name = 'uuid'")
+            .bind(table)
+            .fetch_optional(conn)
+            .await?;
+    Ok(match res {
+        Some(_) => "uuid",
+        None => "NULL",
+    })
+}
+
+/// UUID stored in the app, otherwise derived from the local id
+fn row_uuid(stored: Option<&str>, user: &UserId, kind: &str, id: i64) -> Uuid {
+    stored
+        .and_then(|v| Uuid::parse_str(v).ok())
+        .unwrap_or_else(|| stable_uuid(user, kind, id))
+}
+
+/// Name based UUID (v5) in the namespace of the user
+fn stable_uuid(user: &UserId, kind: &str, id: i64) -> Uuid {
+    let mut hasher = Sha1::new();
+    hasher.update(user.0.as_bytes());
+    hasher.update(format!("vocabletrainer/{}/{}", kind, id).as_bytes());
+    let hash = hasher.finalize();
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    uuid::Builder::from_bytes(bytes)
+        .set_variant(uuid::Variant::RFC4122)
+        .set_version(uuid::Version::Sha1)
+        .build()
+}
+
+/// Convert app times, missing and future times are replaced by now
+fn timestamp(millis: Option<i64>) -> Timestamp {
+    let t_now = Utc::now().naive_utc();
+    millis
+        .and_then(|v| NaiveDateTime::from_timestamp_opt(v / 1000, 0))
+        .map_or(t_now, |v| v.min(t_now))
+}
@@ -13,16 +13,23 @@ use sqlx::Transaction;
 use sqlx::{Connection, MySqlConnection};
 use subtle::ConstantTimeEq;
 
+use super::backup::Backup;
+use super::dedup;
+use super::history::{self, Action};
+use super::import::{validate_entry, MAX_LIST_NAME_LENGTH};
 use super::models::*;
+use super::search;
+use super::sorting;
 use super::*;
+use crate::audit::{AuditLog, Event};
 
 // #[instrument(skip(state,data))]
 pub async fn all_lists(sql: &mut MySqlConnection, user: &UserId) -> Result<HashMap<Uuid, List>> {
-    let sql_fetch =
-        "SELECT uuid,name,name_a,name_b,0 as `foreign`,0 as `change` FROM lists l WHERE l.owner = ?
+    let sql_fetch = "SELECT uuid,name,name_a,name_b,0 as `foreign`,0 as `change` FROM lists l
+    WHERE l.owner = ? AND l.deleted IS NULL
     UNION SELECT uuid,name,name_a,name_b,1,`write` FROM lists l
     JOIN list_permissions p ON p.list = l.uuid
-    WHERE p.user = ?";
+    WHERE p.user = ? AND l.deleted IS NULL";
     // TODO: return is_shared
     let lists: HashMap<Uuid, List> = sqlx::query_as::<_, List>(sql_fetch)
         .bind(user.0)
@@ -43,10 +50,10 @@ pub async fn single_list(sql: &mut MySqlConnection, user: &UserId, list: &ListId
     }
     // FIXME: we're requesting the permission data indirectly in list-perm check already
     let sql_fetch = "SELECT uuid,name,name_a,name_b,0 as `foreign`,0 as `change`
-    FROM lists l WHERE l.owner = ? AND l.uuid = ?
+    FROM lists l WHERE l.owner = ? AND l.uuid = ? AND l.deleted IS NULL
     UNION SELECT uuid,name,name_a,name_b,1,`write` FROM lists l
     JOIN list_permissions p ON p.list = l.uuid
-    WHERE p.user = ? AND l.uuid = ?";
+    WHERE p.user = ? AND l.uuid = ? AND l.deleted IS NULL";
     // TODO: return is_shared
     let lists: List = sqlx::query_as::<_, List>(sql_fetch)
         .bind(user.0)
@@ -98,13 +105,20 @@ pub async fn remove_sharing_user(
     let res = sqlx::query(sql_del)
         .bind(list.0)
         .bind(shared_user.0)
-        .execute(sql)
+        .execute(&mut *sql)
         .await
         .context("fetching shared users")?;
     trace!(
         affected = res.rows_affected(),
         "removed user from shared access"
     );
+    if res.rows_affected() > 0 {
+        AuditLog::new(shared_user, Event::ShareRemoved)
+            .actor(user)
+            .list(&list.0)
+            .write(sql)
+            .await?;
+    }
     Ok(())
 }
 
@@ -119,16 +133,25 @@ pub async fn set_share_permissions(
         return Err(ListError::ListPermission);
     }
     let sql_del = "UPDATE list_permissions SET `write` = ?, `reshare` = ?
-    WHERE p.list = ? AND p.user = ?";
+    WHERE list = ? AND user = ?";
     let res = sqlx::query(sql_del)
         .bind(perms.write)
         .bind(perms.reshare)
         .bind(list.0)
         .bind(shared_user.0)
-        .execute(sql)
+        .execute(&mut *sql)
         .await
         .context("fetching shared users")?;
     trace!(affected = res.rows_affected(), "changed shared user access");
+    if res.rows_affected() > 0 {
+        let detail = format!("write={},reshare={}", perms.write, perms.reshare);
+        AuditLog::new(shared_user, Event::SharePermissionsChanged)
+            .actor(user)
+            .list(&list.0)
+            .detail(&detail)
+            .write(sql)
+            .await?;
+    }
     Ok(())
 }
 
@@ -166,9 +189,18 @@ pub async fn generate_share_code(
         .bind(data.write)
         .bind(data.reshare)
         .bind(data.reusable)
-        .execute(sql)
+        .execute(&mut *sql)
         .await
         .context("inserting sharing token")?;
+    let detail = format!(
+        "write={},reshare={},reusable={}",
+        data.write, data.reshare, data.reusable
+    );
+    AuditLog::new(user, Event::SharecodeCreated)
+        .list(&list.0)
+        .detail(&detail)
+        .write(sql)
+        .await?;
     Ok(ShareTokenReturn {
         token_a: Base64Url::encode_string(token_a.as_slice()),
         token_b: Base64Url::encode_string(token_b.as_slice()),
@@ -241,6 +273,16 @@ async fn _use_share_code(
             {
                 return Err(ListError::SharecodeInvalid);
             }
+            // lists in the trash can't be joined, the code works again after a restore
+            let sql_trashed = "SELECT 1 FROM lists WHERE uuid = ? AND deleted IS NOT NULL";
+            let trashed: Option<i32> = sqlx::query_scalar(sql_trashed)
+                .bind(entry.list)
+                .fetch_optional(&mut *sql)
+                .await
+                .context("checking list trash")?;
+            if trashed.is_some() {
+                return Err(ListError::SharecodeInvalid);
+            }
 
             // TODO: handle user is owner
             let sql_add = "INSERT INTO list_permissions (user,list,`write`,reshare,changed) VALUES (?,?,?,?,?)";
@@ -265,6 +307,17 @@ async fn _use_share_code(
                     .await
                     .context("removing share code")?;
             }
+            // shown to the owner and the redeeming user
+            let (owner,) = sqlx::query_as::<_, (Uuid,)>("SELECT owner FROM lists WHERE uuid = ?")
+                .bind(entry.list)
+                .fetch_one(&mut *sql)
+                .await
+                .context("fetching list owner")?;
+            AuditLog::new(&UserId(owner), Event::SharecodeUsed)
+                .actor(user)
+                .list(&entry.list)
+                .write(&mut *sql)
+                .await?;
             Ok(ListId(entry.list))
         }
     }
@@ -282,6 +335,7 @@ pub async fn change_list(
     if !has_list_perm(&mut transaction, &user, &list, Permission::WRITE).await? {
         return Err(ListError::ListPermission);
     }
+    history::record_list(&mut transaction, &list.0, user, Action::Change).await?;
 
     let sql_change =
         "UPDATE lists SET name = ?, name_a = ?, name_b = ?, `changed` = ? WHERE uuid = ?";
@@ -308,6 +362,7 @@ pub async fn create_list(
 ) -> Result<ListId> {
     let t_now = Utc::now().naive_utc();
     let list = Uuid::new_v4();
+    let mut transaction = sql.begin().await?;
     let sql_create =
         "INSERT INTO lists (owner,uuid,name,name_a,name_b,changed,created) VALUES(?,?,?,?,?,?,?)";
     let res = sqlx::query(sql_create)
@@ -318,14 +373,221 @@ pub async fn create_list(
         .bind(data.name_b)
         .bind(t_now)
         .bind(t_now)
-        .execute(sql)
+        .execute(&mut transaction)
         .await
         .context("updating list")?;
     trace!(list=%list,affected=res.rows_affected(),"updated list");
+    history::record_list(&mut transaction, &list, user, Action::Create).await?;
+    transaction.commit().await?;
 
     Ok(ListId(list))
 }
 
+/// Copy list, entries and meanings into a new list owned by the user.
+/// Requires read permissions for the original.
+pub async fn copy_list(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    list: &ListId,
+    data: ListCopy,
+) -> Result<ListId> {
+    let mut transaction = sql.begin().await?;
+    let res = _copy_list(&mut transaction, user, list, data).await;
+    if res.is_ok() {
+        transaction.commit().await?;
+    } else {
+        transaction.rollback().await?;
+    }
+    res
+}
+
+async fn _copy_list(
+    transaction: &mut Transaction<'_, MySql>,
+    user: &UserId,
+    list: &ListId,
+    data: ListCopy,
+) -> Result<ListId> {
+    if !has_list_perm(&mut *transaction, user, list, Permission::READ).await? {
+        return Err(ListError::ListPermission);
+    }
+    if let Some(name) = data.name.as_deref() {
+        if name.is_empty() || name.chars().count() > MAX_LIST_NAME_LENGTH {
+            return Err(ListError::ValidationError("name"));
+        }
+    }
+    let t_now = Utc::now().naive_utc();
+    let copy = Uuid::new_v4();
+    let source = match data.track_source {
+        true => Some(list.0),
+        false => None,
+    };
+
+    let sql_copy_list = "INSERT INTO lists (owner,uuid,name,name_a,name_b,changed,created,source)
+    SELECT ?,?,IFNULL(?,name),name_a,name_b,?,?,? FROM lists WHERE uuid = ? AND deleted IS NULL";
+    let res = sqlx::query(sql_copy_list)
+        .bind(user.0)
+        .bind(copy)
+        .bind(data.name)
+        .bind(t_now)
+        .bind(t_now)
+        .bind(source)
+        .bind(list.0)
+        .execute(&mut *transaction)
+        .await
+        .context("copying list")?;
+    if res.rows_affected() != 1 {
+        return Err(ListError::ListNotFound);
+    }
+    history::record_list(&mut *transaction, &copy, user, Action::Create).await?;
+
+    let sql_entries = "SELECT uuid FROM entries WHERE list = ?";
+    let entries: Vec<Uuid> = sqlx::query_scalar::<_, Uuid>(sql_entries)
+        .bind(list.0)
+        .fetch(&mut *transaction)
+        .try_collect()
+        .await
+        .context("fetching entries")?;
+
+    let sql_copy_entry = "INSERT INTO entries
+    (list,uuid,created,`changed`,updated,tip,source,created_by,changed_by,sort_a,sort_b)
+    SELECT ?,?,?,?,?,tip,?,?,?,sort_a,sort_b FROM entries WHERE uuid = ?";
+    let sql_copy_meanings = "INSERT INTO entry_meaning (entry,value,is_a)
+    SELECT ?,value,is_a FROM entry_meaning WHERE entry = ?";
+    for entry in entries.iter() {
+        let entry_copy = Uuid::new_v4();
+        sqlx::query(sql_copy_entry)
+            .bind(copy)
+            .bind(entry_copy)
+            .bind(t_now)
+            .bind(t_now)
+            .bind(t_now)
+            .bind(source.map(|_| entry))
+            .bind(user.0)
+            .bind(user.0)
+            .bind(entry)
+            .execute(&mut *transaction)
+            .await
+            .context("copying entry")?;
+        sqlx::query(sql_copy_meanings)
+            .bind(entry_copy)
+            .bind(entry)
+            .execute(&mut *transaction)
+            .await
+            .context("copying meanings")?;
+        history::record_entry(&mut *transaction, &entry_copy, user, Action::Create).await?;
+    }
+    trace!(list=%list,copy=%copy,entries=entries.len(),"copied list");
+
+    Ok(ListId(copy))
+}
+
+/// Merge the source list into the target list, owner only.
+/// Duplicate entries are merged into the existing target entry and deleted from the source,
+/// the source list is deleted afterwards unless its entries are copied.
+pub async fn merge_lists(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    list: &ListId,
+    data: ListMerge,
+) -> Result<MergeReport> {
+    let mut transaction = sql.begin().await?;
+    let res = _merge_lists(&mut transaction, user, list, data).await;
+    if res.is_ok() {
+        transaction.commit().await?;
+    } else {
+        transaction.rollback().await?;
+    }
+    res
+}
+
+async fn _merge_lists(
+    transaction: &mut Transaction<'_, MySql>,
+    user: &UserId,
+    list: &ListId,
+    data: ListMerge,
+) -> Result<MergeReport> {
+    let source = ListId(data.source);
+    if source.0 == list.0 {
+        return Err(ListError::ValidationError("source"));
+    }
+    if !has_list_perm(&mut *transaction, user, list, Permission::OWNER).await?
+        || !has_list_perm(&mut *transaction, user, &source, Permission::OWNER).await?
+    {
+        return Err(ListError::ListPermission);
+    }
+    let t_now = Utc::now().naive_utc();
+    let mut report = MergeReport {
+        entries: 0,
+        merged: 0,
+    };
+
+    let mut targets = list_entries(&mut *transaction, list).await?;
+    let sql_move_entry = "UPDATE entries SET list = ?, updated = ?, changed_by = ? WHERE uuid = ?";
+    for entry in list_entries(&mut *transaction, &source).await? {
+        let duplicate = targets
+            .iter_mut()
+            .find(|t| dedup::is_duplicate(&t.meanings, &entry.meanings));
+        if let Some(target) = duplicate {
+            let source_entry = EntryId(entry.uuid);
+            let mut merged = target.clone();
+            if dedup::merge_entry(&mut merged, entry) {
+                let target_id = EntryId(target.uuid);
+                history::record_entry(&mut *transaction, &target.uuid, user, Action::Change)
+                    .await?;
+                let change = EntryChange {
+                    tip: merged.tip.clone(),
+                    meanings: merged.meanings.iter().cloned().map(Into::into).collect(),
+                };
+                update_entry(&mut *transaction, user, &target_id, change).await?;
+                *target = merged;
+            }
+            if !data.copy {
+                _delete_entry(&mut *transaction, user, source_entry).await?;
+            }
+            report.merged += 1;
+            continue;
+        }
+
+        let moved = if data.copy {
+            let create = EntryCreate {
+                tip: entry.tip.clone(),
+                meanings: entry.meanings.iter().cloned().map(Into::into).collect(),
+            };
+            let id = insert_entry(&mut *transaction, user, list, create).await?;
+            dedup::StoredEntry {
+                uuid: id.0,
+                ..entry
+            }
+        } else {
+            sqlx::query(sql_move_entry)
+                .bind(list.0)
+                .bind(t_now)
+                .bind(user.0)
+                .bind(entry.uuid)
+                .execute(&mut *transaction)
+                .await
+                .context("moving entry")?;
+            history::record_entry(&mut *transaction, &entry.uuid, user, Action::Move).await?;
+            entry
+        };
+        // later source entries can be duplicates of this one
+        targets.push(moved);
+        report.entries += 1;
+    }
+
+    if !data.copy {
+        _delete_list(&mut *transaction, user, source).await?;
+    }
+    trace!(
+        list=%list,
+        source=%data.source,
+        entries = report.entries,
+        merged = report.merged,
+        "merged lists"
+    );
+    Ok(report)
+}
+
 pub async fn delete_list(sql: &mut MySqlConnection, user: &UserId, list: ListId) -> Result<()> {
     let mut transaction = sql.begin().await?;
     let res = _delete_list(&mut transaction, user, list).await;
@@ -348,6 +610,7 @@ async fn _delete_list(
 
     let t_now = Utc::now().naive_utc();
 
+    // tombstones for sync, removed again on restore
     let sql_tombstone = "INSERT INTO deleted_list (user,list,created) VALUES (?,?,?)";
     sqlx::query(sql_tombstone)
         .bind(user.0)
@@ -356,8 +619,18 @@ async fn _delete_list(
         .execute(&mut *transaction)
         .await
         .context("inserting list tombstone")?;
-    let sql_del_list = "DELETE FROM lists WHERE uuid = ?";
-    let res = sqlx::query(sql_del_list)
+    let sql_tombstone_shared = "INSERT INTO deleted_list_shared (user,list,created)
+    SELECT user,list,? FROM list_permissions WHERE list = ?";
+    sqlx::query(sql_tombstone_shared)
+        .bind(t_now)
+        .bind(list.0)
+        .execute(&mut *transaction)
+        .await
+        .context("inserting shared list tombstones")?;
+    // move to trash, entries and permissions are kept until purged
+    let sql_trash_list = "UPDATE lists SET deleted = ? WHERE uuid = ?";
+    let res = sqlx::query(sql_trash_list)
+        .bind(t_now)
         .bind(list.0)
         .execute(&mut *transaction)
         .await
@@ -372,46 +645,278 @@ async fn _delete_list(
     Ok(())
 }
 
-// #[instrument(skip(state,data))]
-pub async fn entries(
+/// Deleted lists of the user, purged `retention` days after their deletion
+pub async fn trash(
     sql: &mut MySqlConnection,
     user: &UserId,
+    retention: u32,
+) -> Result<Vec<TrashedList>> {
+    let sql_fetch = "SELECT uuid,name,name_a,name_b,deleted,
+    DATE_ADD(deleted, INTERVAL ? DAY) AS purge_at
+    FROM lists WHERE owner = ? AND deleted IS NOT NULL ORDER BY deleted DESC";
+    let lists = sqlx::query_as::<_, TrashedList>(sql_fetch)
+        .bind(retention)
+        .bind(user.0)
+        .fetch(sql)
+        .try_collect()
+        .await
+        .context("fetching trash")?;
+    Ok(lists)
+}
+
+pub async fn restore_list(sql: &mut MySqlConnection, user: &UserId, list: ListId) -> Result<()> {
+    let mut transaction = sql.begin().await?;
+    let res = _restore_list(&mut transaction, user, list).await;
+    if res.is_ok() {
+        transaction.commit().await?;
+    } else {
+        transaction.rollback().await?;
+    }
+    res
+}
+
+/// Restore a list from the trash.
+/// Sync clients received the deletion already, so the list, its entries
+/// and shares are marked as changed to be transferred again.
+async fn _restore_list(
+    transaction: &mut Transaction<'_, MySql>,
+    user: &UserId,
     list: ListId,
-) -> Result<HashMap<Uuid, Entry>> {
-    if !has_list_perm(&mut *sql, &user, &list, Permission::READ).await? {
+) -> Result<()> {
+    let sql_owner = "SELECT owner,deleted FROM lists WHERE uuid = ? FOR UPDATE";
+    let (owner, deleted) = sqlx::query_as::<_, (Uuid, Option<Timestamp>)>(sql_owner)
+        .bind(list.0)
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("fetching list owner")?
+        .ok_or(ListError::ListNotFound)?;
+    if owner != user.0 {
+        return Err(ListError::ListPermission);
+    }
+    if deleted.is_none() {
+        return Err(ListError::ListNotFound);
+    }
+
+    let t_now = Utc::now().naive_utc();
+    let sql_restore = "UPDATE lists SET deleted = NULL, changed = ? WHERE uuid = ?";
+    sqlx::query(sql_restore)
+        .bind(t_now)
+        .bind(list.0)
+        .execute(&mut *transaction)
+        .await
+        .context("restoring list")?;
+    let sql_del_tombstone = "DELETE FROM deleted_list WHERE list = ?";
+    sqlx::query(sql_del_tombstone)
+        .bind(list.0)
+        .execute(&mut *transaction)
+        .await
+        .context("removing list tombstone")?;
+    // users unshared while the list was in the trash keep their tombstone
+    let sql_del_tombstone_shared = "DELETE d FROM deleted_list_shared d
+    JOIN list_permissions p ON p.list = d.list AND p.user = d.user
+    WHERE d.list = ?";
+    sqlx::query(sql_del_tombstone_shared)
+        .bind(list.0)
+        .execute(&mut *transaction)
+        .await
+        .context("removing shared list tombstones")?;
+    let sql_entries = "UPDATE entries SET updated = ? WHERE list = ?";
+    let res = sqlx::query(sql_entries)
+        .bind(t_now)
+        .bind(list.0)
+        .execute(&mut *transaction)
+        .await
+        .context("updating entries")?;
+    trace!(list=%list,affected=res.rows_affected(),"restored entries");
+    let sql_perms = "UPDATE list_permissions SET changed = ? WHERE list = ?";
+    sqlx::query(sql_perms)
+        .bind(t_now)
+        .bind(list.0)
+        .execute(&mut *transaction)
+        .await
+        .context("updating shared users")?;
+    Ok(())
+}
+
+/// Delete lists which are in the trash for more than `retention` days.
+/// Tombstones are kept for sync clients. Returns the amount of purged lists.
+pub async fn purge_trash(sql: &mut MySqlConnection, retention: u32) -> Result<u64> {
+    let before = Utc::now().naive_utc() - chrono::Duration::days(retention.into());
+    let res = sqlx::query("DELETE FROM lists WHERE deleted < ?")
+        .bind(before)
+        .execute(sql)
+        .await
+        .context("purging trash")?;
+    trace!(affected = res.rows_affected(), "purged trash");
+    Ok(res.rows_affected())
+}
+
+/// Maximum entries returned per page
+const MAX_ENTRY_PAGE_LIMIT: u32 = 500;
+/// uuid, tip, created, changed, text sort key, created_by, changed_by
+type EntryPageRow = (
+    Uuid,
+    Option<String>,
+    Timestamp,
+    Timestamp,
+    String,
+    Option<Uuid>,
+    Option<Uuid>,
+);
+/// Format of time sort keys in entry cursors
+const CURSOR_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// Ordered page of entries, continuing after the cursor of the query
+pub async fn entry_page(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    list: &ListId,
+    query: EntriesQuery,
+) -> Result<EntryPage> {
+    if !has_list_perm(&mut *sql, user, list, Permission::READ).await? {
         return Err(ListError::ListPermission);
     }
-    let sql_entry = "SELECT uuid,tip FROM entries e 
-    WHERE e.list = ?";
-    let raw_e: Vec<(Uuid, String)> = sqlx::query_as::<_, (Uuid, String)>(sql_entry)
+    let cursor = match query.after.as_deref() {
+        Some(v) => {
+            let raw = Base64Url::decode_vec(v).map_err(|_| ListError::ValidationError("after"))?;
+            let cursor: EntryCursor =
+                serde_json::from_slice(&raw).map_err(|_| ListError::ValidationError("after"))?;
+            Some(cursor)
+        }
+        None => None,
+    };
+    let key = match query.sort {
+        EntrySort::Created => "e.created",
+        EntrySort::Changed => "e.changed",
+        EntrySort::A => "e.sort_a",
+        EntrySort::B => "e.sort_b",
+    };
+    // times are read from their own columns
+    let text_key = match query.sort {
+        EntrySort::Created | EntrySort::Changed => "''",
+        EntrySort::A | EntrySort::B => key,
+    };
+    let (cmp, dir) = match query.desc {
+        true => ("<", "DESC"),
+        false => (">", "ASC"),
+    };
+    let sql_cursor = match cursor {
+        Some(_) => format!(
+            "AND ({key} {cmp} ? OR ({key} = ? AND e.uuid {cmp} ?))",
+            key = key,
+            cmp = cmp
+        ),
+        None => String::new(),
+    };
+    let sql_page = format!(
+        "SELECT e.uuid,e.tip,e.created,e.changed,{text_key} AS text_key,e.created_by,e.changed_by
+        FROM entries e
+        WHERE e.list = ? AND (? IS NULL OR e.changed >= ?) AND (? IS NULL OR e.changed <= ?)
+        {cursor}
+        ORDER BY {key} {dir}, e.uuid {dir} LIMIT ?",
+        text_key = text_key,
+        key = key,
+        cursor = sql_cursor,
+        dir = dir
+    );
+    let limit = query.limit.clamp(1, MAX_ENTRY_PAGE_LIMIT);
+    let mut q = sqlx::query_as::<_, EntryPageRow>(&sql_page)
         .bind(list.0)
+        .bind(query.changed_from)
+        .bind(query.changed_from)
+        .bind(query.changed_to)
+        .bind(query.changed_to);
+    if let Some(cursor) = cursor {
+        q = match query.sort {
+            EntrySort::Created | EntrySort::Changed => {
+                let time = Timestamp::parse_from_str(&cursor.key, CURSOR_TIME_FORMAT)
+                    .map_err(|_| ListError::ValidationError("after"))?;
+                q.bind(time).bind(time)
+            }
+            EntrySort::A | EntrySort::B => q.bind(cursor.key.clone()).bind(cursor.key),
+        }
+        .bind(cursor.entry);
+    }
+    let mut rows: Vec<EntryPageRow> = q
+        .bind(limit + 1)
         .fetch(&mut *sql)
         .try_collect()
         .await
-        .context("fetching entries")?;
+        .context("fetching entry page")?;
+
+    let next = if rows.len() > limit as usize {
+        rows.truncate(limit as usize);
+        let (uuid, _, created, changed, text_key, _, _) = &rows[rows.len() - 1];
+        let key = match query.sort {
+            EntrySort::Created => created.format(CURSOR_TIME_FORMAT).to_string(),
+            EntrySort::Changed => changed.format(CURSOR_TIME_FORMAT).to_string(),
+            EntrySort::A | EntrySort::B => text_key.clone(),
+        };
+        let cursor = serde_json::to_vec(&EntryCursor { key, entry: *uuid })?;
+        Some(Base64Url::encode_string(&cursor))
+    } else {
+        None
+    };
 
     let sql_meanings = "SELECT value,is_a FROM entry_meaning WHERE entry = ?";
-    let mut entries = HashMap::with_capacity(raw_e.len());
-    for (uuid, tip) in raw_e {
+    let mut entries = Vec::with_capacity(rows.len());
+    for (uuid, tip, created, _, _, created_by, changed_by) in rows {
         let meanings = sqlx::query_as::<_, EntryMeaning>(sql_meanings)
-            .bind(&uuid)
+            .bind(uuid)
             .fetch(&mut *sql)
             .try_collect()
             .await
             .context("fetching meanings")?;
-        entries.insert(
-            uuid.clone(),
-            Entry {
-                tip,
-                uuid,
-                meanings,
-            },
-        );
+        entries.push(Entry {
+            tip: tip.unwrap_or_default(),
+            uuid,
+            meanings,
+            created,
+            created_by,
+            changed_by,
+        });
     }
 
+    Ok(EntryPage { entries, next })
+}
+
+/// All entries of a list with their stored meanings, without permission checks
+async fn list_entries(sql: &mut MySqlConnection, list: &ListId) -> Result<Vec<dedup::StoredEntry>> {
+    let sql_entry = "SELECT uuid,tip FROM entries e WHERE e.list = ?";
+    // tips synced from clients can be NULL
+    let raw_e: Vec<(Uuid, Option<String>)> = sqlx::query_as(sql_entry)
+        .bind(list.0)
+        .fetch(&mut *sql)
+        .try_collect()
+        .await
+        .context("fetching entries")?;
+
+    let mut entries = Vec::with_capacity(raw_e.len());
+    for (uuid, tip) in raw_e {
+        entries.push(dedup::StoredEntry {
+            meanings: stored_meanings(&mut *sql, &uuid).await?,
+            tip: tip.unwrap_or_default(),
+            uuid,
+        });
+    }
     Ok(entries)
 }
 
+/// Meanings of the entry with their normalized values
+async fn stored_meanings(
+    sql: &mut MySqlConnection,
+    entry: &Uuid,
+) -> Result<Vec<dedup::StoredMeaning>> {
+    let sql_meanings = "SELECT value,is_a,normalized FROM entry_meaning WHERE entry = ?";
+    let meanings = sqlx::query_as::<_, dedup::StoredMeaning>(sql_meanings)
+        .bind(entry)
+        .fetch(&mut *sql)
+        .try_collect()
+        .await
+        .context("fetching meanings")?;
+    Ok(meanings)
+}
+
 pub async fn change_entry(
     sql: &mut MySqlConnection,
     user: &UserId,
@@ -435,29 +940,42 @@ async fn _change_entry(
     entry: EntryId,
     data: EntryChange,
 ) -> Result<()> {
-    let t_now = Utc::now().naive_utc();
     let list = list_of_entry(&mut *transaction, &entry).await?;
     if !has_list_perm(&mut *transaction, &user, &list, Permission::WRITE).await? {
         return Err(ListError::ListPermission);
     }
+    history::record_entry(&mut *transaction, &entry.0, user, Action::Change).await?;
+    update_entry(&mut *transaction, user, &entry, data).await
+}
 
-    let sql_change = "UPDATE entries SET tip = ?, `changed` = ? WHERE uuid = ?";
+/// Overwrite tip and meanings of an existing entry, without permission checks
+async fn update_entry(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    entry: &EntryId,
+    data: EntryChange,
+) -> Result<()> {
+    let t_now = Utc::now().naive_utc();
+    let sql_change =
+        "UPDATE entries SET tip = ?, `changed` = ?, updated = ?, changed_by = ? WHERE uuid = ?";
     let res = sqlx::query(sql_change)
         .bind(data.tip)
         .bind(t_now)
+        .bind(t_now)
+        .bind(user.0)
         .bind(entry.0)
-        .execute(&mut *transaction)
+        .execute(&mut *sql)
         .await
         .context("updating entry")?;
-    trace!(list=%list,affected=res.rows_affected(),"updated entry");
+    trace!(entry=%entry,affected=res.rows_affected(),"updated entry");
 
     let sql_del_meaning = "DELETE FROM entry_meaning WHERE entry = ?";
     let res = sqlx::query(sql_del_meaning)
         .bind(entry.0)
-        .execute(&mut *transaction)
+        .execute(&mut *sql)
         .await
         .context("deleting meanings")?;
-    trace!(list=%list,affected=res.rows_affected(),"deleted meanings");
+    trace!(entry=%entry,affected=res.rows_affected(),"deleted meanings");
 
     let sql_meaning = "INSERT INTO entry_meaning (entry,value,is_a) VALUES(?,?,?)";
     for m in data.meanings {
@@ -465,10 +983,11 @@ async fn _change_entry(
             .bind(entry.0)
             .bind(m.value)
             .bind(m.is_a)
-            .execute(&mut *transaction)
+            .execute(&mut *sql)
             .await
             .context("inserting meanings")?;
     }
+    sorting::update_sort_keys(sql, &entry.0).await?;
     Ok(())
 }
 
@@ -477,23 +996,297 @@ pub async fn create_entry(
     user: UserId,
     list: ListId,
     data: EntryCreate,
+    reject_duplicates: bool,
 ) -> Result<EntryId> {
-    let t_now = Utc::now().naive_utc();
     let mut transaction = sql.begin().await?;
     if !has_list_perm(&mut transaction, &user, &list, Permission::WRITE).await? {
         return Err(ListError::ListPermission);
     }
+    let entry = insert_entry(&mut transaction, &user, &list, data).await?;
+    // compared as stored, dropping the transaction removes the entry again
+    if reject_duplicates {
+        if let Some(existing) = find_duplicate(&mut transaction, &list, &entry).await? {
+            return Err(ListError::DuplicateEntry(existing.0));
+        }
+    }
+
+    transaction.commit().await?;
+
+    Ok(entry)
+}
+
+/// Other entry of the list which is a duplicate of the entry, see [dedup::is_duplicate]
+async fn find_duplicate(
+    sql: &mut MySqlConnection,
+    list: &ListId,
+    entry: &EntryId,
+) -> Result<Option<EntryId>> {
+    let sql_candidates = "SELECT DISTINCT m.entry FROM entry_meaning m
+    JOIN entry_meaning n ON n.normalized = m.normalized AND n.is_a = m.is_a
+    JOIN entries e ON e.uuid = m.entry
+    WHERE n.entry = ? AND e.list = ? AND m.entry <> n.entry";
+    let candidates: Vec<Uuid> = sqlx::query_scalar::<_, Uuid>(sql_candidates)
+        .bind(entry.0)
+        .bind(list.0)
+        .fetch(&mut *sql)
+        .try_collect()
+        .await
+        .context("fetching duplicate candidates")?;
+
+    let meanings = stored_meanings(&mut *sql, &entry.0).await?;
+    for candidate in candidates {
+        let existing = stored_meanings(&mut *sql, &candidate).await?;
+        if dedup::is_duplicate(&existing, &meanings) {
+            return Ok(Some(EntryId(candidate)));
+        }
+    }
+    Ok(None)
+}
+
+/// Meanings occurring in multiple entries of the list, requires read permissions
+pub async fn duplicates(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    list: &ListId,
+) -> Result<Vec<Duplicate>> {
+    if !has_list_perm(&mut *sql, user, list, Permission::READ).await? {
+        return Err(ListError::ListPermission);
+    }
+    let sql_duplicates = "SELECT DISTINCT m.normalized,m.is_a,m.entry FROM entry_meaning m
+    JOIN entries e ON e.uuid = m.entry
+    JOIN (SELECT m2.normalized,m2.is_a FROM entry_meaning m2
+        JOIN entries e2 ON e2.uuid = m2.entry
+        WHERE e2.list = ?
+        GROUP BY m2.normalized,m2.is_a
+        HAVING COUNT(DISTINCT m2.entry) > 1) d
+    ON d.normalized = m.normalized AND d.is_a = m.is_a
+    WHERE e.list = ?
+    ORDER BY m.normalized,m.is_a,m.entry";
+    let rows: Vec<(String, bool, Uuid)> = sqlx::query_as(sql_duplicates)
+        .bind(list.0)
+        .bind(list.0)
+        .fetch(&mut *sql)
+        .try_collect()
+        .await
+        .context("fetching duplicates")?;
+
+    let mut duplicates: Vec<Duplicate> = Vec::new();
+    for (value, is_a, entry) in rows {
+        match duplicates.last_mut() {
+            Some(d) if d.value == value && d.is_a == is_a => d.entries.push(entry),
+            _ => duplicates.push(Duplicate {
+                value,
+                is_a,
+                entries: vec![entry],
+            }),
+        }
+    }
+    Ok(duplicates)
+}
+
+/// Insert imported entries in one transaction, returns the amount inserted.
+/// Only checks the permissions for dry runs.
+pub async fn import_entries(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    list: &ListId,
+    entries: Vec<EntryCreate>,
+    dry_run: bool,
+) -> Result<usize> {
+    let mut transaction = sql.begin().await?;
+    if !has_list_perm(&mut transaction, user, list, Permission::WRITE).await? {
+        return Err(ListError::ListPermission);
+    }
+    if dry_run {
+        return Ok(0);
+    }
+
+    let amount = entries.len();
+    for data in entries {
+        insert_entry(&mut transaction, user, list, data).await?;
+    }
+    transaction.commit().await?;
+    trace!(list=%list,amount,"imported entries");
+    Ok(amount)
+}
+
+/// Import lists of a VocableTrainer backup for the user in one transaction.
+/// Lists and entries already existing or deleted on the server are skipped,
+/// importing the same backup again only adds what's missing.
+pub async fn import_backup(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    backup: Backup,
+) -> Result<BackupImportReport> {
+    let mut transaction = sql.begin().await?;
+    let res = _import_backup(&mut transaction, user, backup).await;
+    if res.is_ok() {
+        transaction.commit().await?;
+    } else {
+        transaction.rollback().await?;
+    }
+    res
+}
+
+async fn _import_backup(
+    transaction: &mut Transaction<'_, MySql>,
+    user: &UserId,
+    backup: Backup,
+) -> Result<BackupImportReport> {
+    let t_now = Utc::now().naive_utc();
+    let mut report = BackupImportReport::default();
+
+    let sql_category_exists = "SELECT 1 FROM category WHERE uuid = ?
+    UNION SELECT 1 FROM deleted_category WHERE category = ?";
+    let sql_category = "INSERT INTO category (owner,uuid,name,changed) VALUES (?,?,?,?)";
+    for c in backup.categories {
+        let exists: Option<i32> = sqlx::query_scalar(sql_category_exists)
+            .bind(c.uuid)
+            .bind(c.uuid)
+            .fetch_optional(&mut *transaction)
+            .await
+            .context("checking category")?;
+        if exists.is_some() {
+            continue;
+        }
+        sqlx::query(sql_category)
+            .bind(user.0)
+            .bind(c.uuid)
+            .bind(truncate(&c.name, MAX_LIST_NAME_LENGTH))
+            .bind(t_now)
+            .execute(&mut *transaction)
+            .await
+            .context("inserting category")?;
+        report.categories += 1;
+    }
+
+    let sql_list_exists = "SELECT 1 FROM lists WHERE uuid = ?
+    UNION SELECT 1 FROM deleted_list WHERE list = ?";
+    let sql_list = "INSERT INTO lists (owner,uuid,name,name_a,name_b,changed,created)
+    VALUES (?,?,?,?,?,?,?)";
+    let sql_list_category = "INSERT IGNORE INTO list_category (list,category)
+    SELECT ?,uuid FROM category WHERE uuid = ? AND owner = ?";
+    let sql_entry_exists = "SELECT 1 FROM entries WHERE uuid = ?
+    UNION SELECT 1 FROM deleted_entry WHERE `entry` = ?";
+    let sql_entry =
+        "INSERT INTO entries (list,uuid,created,`changed`,updated,tip,created_by,changed_by)
+    VALUES (?,?,?,?,?,?,?,?)";
+    let sql_meaning = "INSERT INTO entry_meaning (entry,value,is_a) VALUES (?,?,?)";
+    for list in backup.lists {
+        let exists: Option<i32> = sqlx::query_scalar(sql_list_exists)
+            .bind(list.uuid)
+            .bind(list.uuid)
+            .fetch_optional(&mut *transaction)
+            .await
+            .context("checking list")?;
+        if exists.is_some() {
+            report.skipped_lists.push(list.uuid);
+            continue;
+        }
+        sqlx::query(sql_list)
+            .bind(user.0)
+            .bind(list.uuid)
+            .bind(truncate(&list.name, MAX_LIST_NAME_LENGTH))
+            .bind(truncate(&list.name_a, MAX_LIST_NAME_LENGTH))
+            .bind(truncate(&list.name_b, MAX_LIST_NAME_LENGTH))
+            .bind(list.changed)
+            .bind(list.created)
+            .execute(&mut *transaction)
+            .await
+            .context("inserting list")?;
+        history::record_list(&mut *transaction, &list.uuid, user, Action::Create).await?;
+        for category in list.categories.iter() {
+            sqlx::query(sql_list_category)
+                .bind(list.uuid)
+                .bind(category)
+                .bind(user.0)
+                .execute(&mut *transaction)
+                .await
+                .context("inserting list category")?;
+        }
+
+        for entry in list.entries {
+            if let Err(error) = validate_entry(&entry.tip, &entry.meanings) {
+                report.errors.push(BackupEntryError {
+                    list: list.uuid,
+                    entry: entry.id,
+                    error,
+                });
+                continue;
+            }
+            let exists: Option<i32> = sqlx::query_scalar(sql_entry_exists)
+                .bind(entry.uuid)
+                .bind(entry.uuid)
+                .fetch_optional(&mut *transaction)
+                .await
+                .context("checking entry")?;
+            if exists.is_some() {
+                report.skipped_entries += 1;
+                continue;
+            }
+            sqlx::query(sql_entry)
+                .bind(list.uuid)
+                .bind(entry.uuid)
+                .bind(entry.created)
+                .bind(entry.changed)
+                .bind(t_now)
+                .bind(&entry.tip)
+                .bind(user.0)
+                .bind(user.0)
+                .execute(&mut *transaction)
+                .await
+                .context("inserting entry")?;
+            for m in entry.meanings {
+                sqlx::query(sql_meaning)
+                    .bind(entry.uuid)
+                    .bind(m.value)
+                    .bind(m.is_a)
+                    .execute(&mut *transaction)
+                    .await
+                    .context("inserting meanings")?;
+            }
+            sorting::update_sort_keys(&mut *transaction, &entry.uuid).await?;
+            history::record_entry(&mut *transaction, &entry.uuid, user, Action::Create).await?;
+            report.entries += 1;
+        }
+        report.lists.push(list.uuid);
+    }
+    trace!(
+        user=%user,
+        lists = report.lists.len(),
+        entries = report.entries,
+        "imported backup"
+    );
+    Ok(report)
+}
+
+fn truncate(value: &str, length: usize) -> String {
+    value.chars().take(length).collect()
+}
 
+/// Insert a new entry with its meanings and creation revision, without permission checks
+async fn insert_entry(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    list: &ListId,
+    data: EntryCreate,
+) -> Result<EntryId> {
+    let t_now = Utc::now().naive_utc();
     let entry = Uuid::new_v4();
 
-    let sql_change = "INSERT INTO entries (list,uuid,`changed`,updated,tip) VALUES(?,?,?,?,?)";
+    let sql_change =
+        "INSERT INTO entries (list,uuid,created,`changed`,updated,tip,created_by,changed_by)
+    VALUES(?,?,?,?,?,?,?,?)";
     let res = sqlx::query(sql_change)
         .bind(list.0)
         .bind(entry)
         .bind(t_now)
         .bind(t_now)
+        .bind(t_now)
         .bind(data.tip)
-        .execute(&mut transaction)
+        .bind(user.0)
+        .bind(user.0)
+        .execute(&mut *sql)
         .await
         .context("inserting entry")?;
     trace!(list=%list,affected=res.rows_affected(),"inserting entry");
@@ -504,13 +1297,12 @@ pub async fn create_entry(
             .bind(entry)
             .bind(m.value)
             .bind(m.is_a)
-            .execute(&mut transaction)
+            .execute(&mut *sql)
             .await
             .context("inserting meanings")?;
     }
-
-    transaction.commit().await?;
-
+    sorting::update_sort_keys(&mut *sql, &entry).await?;
+    history::record_entry(sql, &entry, user, Action::Create).await?;
     Ok(EntryId(entry))
 }
 
@@ -544,6 +1336,7 @@ async fn _delete_entry(
         .execute(&mut *transaction)
         .await
         .context("inserting list tombstone")?;
+    history::record_entry(&mut *transaction, &entry.0, user, Action::Delete).await?;
 
     let sql_del_entry = "DELETE FROM entries WHERE uuid = ?";
     let res = sqlx::query(sql_del_entry)
@@ -555,6 +1348,138 @@ async fn _delete_entry(
     Ok(())
 }
 
+/// Revisions of the list and its entries, newest first. `before` continues after the given id.
+pub async fn history(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    list: &ListId,
+    before: Option<u64>,
+    limit: u32,
+) -> Result<Vec<Revision>> {
+    if !has_list_perm(&mut *sql, user, list, Permission::READ).await? {
+        return Err(ListError::ListPermission);
+    }
+    let sql_fetch = "SELECT r.id,r.entry,r.user,u.name AS user_name,r.action,r.created,
+    r.name,r.name_a,r.name_b,r.tip FROM revision r
+    LEFT JOIN users u ON u.uuid = r.user
+    WHERE r.list = ? AND r.id < ? ORDER BY r.id DESC LIMIT ?";
+    let raw: Vec<RevisionBlank> = sqlx::query_as::<_, RevisionBlank>(sql_fetch)
+        .bind(list.0)
+        .bind(before.unwrap_or(u64::MAX))
+        .bind(limit.min(history::MAX_HISTORY_LIMIT))
+        .fetch(&mut *sql)
+        .try_collect()
+        .await
+        .context("fetching revisions")?;
+
+    let mut revisions = Vec::with_capacity(raw.len());
+    for rev in raw {
+        let meanings = match rev.entry {
+            Some(_) => revision_meanings(&mut *sql, rev.id).await?,
+            None => Vec::new(),
+        };
+        revisions.push(rev.into_full(meanings));
+    }
+    Ok(revisions)
+}
+
+async fn revision_meanings(sql: &mut MySqlConnection, revision: u64) -> Result<Vec<EntryMeaning>> {
+    let sql_meanings = "SELECT value,is_a FROM revision_meaning WHERE revision = ?";
+    let meanings = sqlx::query_as::<_, EntryMeaning>(sql_meanings)
+        .bind(revision)
+        .fetch(sql)
+        .try_collect()
+        .await
+        .context("fetching revision meanings")?;
+    Ok(meanings)
+}
+
+pub async fn revert_revision(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    list: ListId,
+    revision: u64,
+) -> Result<Option<EntryId>> {
+    let mut transaction = sql.begin().await?;
+    let res = _revert_revision(&mut transaction, user, list, revision).await;
+    if res.is_ok() {
+        transaction.commit().await?;
+    } else {
+        transaction.rollback().await?;
+    }
+    res
+}
+
+/// Restore the values stored in the revision, itself recorded as revision.
+/// Deleted entries are re-created with a new id as their tombstone is final for sync.
+/// Returns the entry for entry revisions.
+async fn _revert_revision(
+    transaction: &mut Transaction<'_, MySql>,
+    user: &UserId,
+    list: ListId,
+    revision: u64,
+) -> Result<Option<EntryId>> {
+    if !has_list_perm(&mut *transaction, user, &list, Permission::WRITE).await? {
+        return Err(ListError::ListPermission);
+    }
+    let sql_revision = "SELECT `entry`,name,name_a,name_b,tip FROM revision
+    WHERE id = ? AND list = ?";
+    type RevisionRow = (
+        Option<Uuid>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+    );
+    let (entry, name, name_a, name_b, tip) = sqlx::query_as::<_, RevisionRow>(sql_revision)
+        .bind(revision)
+        .bind(list.0)
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("fetching revision")?
+        .ok_or(ListError::RevisionNotFound)?;
+
+    let entry = match entry {
+        Some(v) => EntryId(v),
+        None => {
+            let t_now = Utc::now().naive_utc();
+            history::record_list(&mut *transaction, &list.0, user, Action::Revert).await?;
+            let sql_change =
+                "UPDATE lists SET name = ?, name_a = ?, name_b = ?, `changed` = ? WHERE uuid = ?";
+            let res = sqlx::query(sql_change)
+                .bind(name.unwrap_or_default())
+                .bind(name_a.unwrap_or_default())
+                .bind(name_b.unwrap_or_default())
+                .bind(t_now)
+                .bind(list.0)
+                .execute(&mut *transaction)
+                .await
+                .context("reverting list")?;
+            trace!(list=%list,revision,affected=res.rows_affected(),"reverted list");
+            return Ok(None);
+        }
+    };
+    let data = EntryChange {
+        tip: tip.unwrap_or_default(),
+        meanings: revision_meanings(&mut *transaction, revision).await?,
+    };
+    let existing = match list_of_entry(&mut *transaction, &entry).await {
+        Ok(v) => v.0 == list.0,
+        Err(ListError::ListNotFound) => false,
+        Err(e) => return Err(e),
+    };
+    if existing {
+        history::record_entry(&mut *transaction, &entry.0, user, Action::Revert).await?;
+        update_entry(&mut *transaction, user, &entry, data).await?;
+        trace!(entry=%entry,revision,"reverted entry");
+        Ok(Some(entry))
+    } else {
+        let new_entry = insert_entry(&mut *transaction, user, &list, data).await?;
+        trace!(entry=%entry,%new_entry,revision,"re-created entry");
+        Ok(Some(new_entry))
+    }
+}
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Eq, PartialEq)]
 pub enum Permission {
@@ -578,15 +1503,86 @@ async fn list_of_entry(sql: &mut MySqlConnection, entry: &EntryId) -> Result<Lis
     Ok(ListId(list))
 }
 
+/// Search list names, tips and meanings of all lists accessible by the user, best matches first
+pub async fn search(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    query: SearchQuery,
+) -> Result<Vec<SearchHit>> {
+    if query.q.chars().count() > search::MAX_QUERY_LENGTH {
+        return Err(ListError::ValidationError("q"));
+    }
+    let terms = search::boolean_query(&query.q).ok_or(ListError::ValidationError("q"))?;
+
+    // visibility is joined in each branch, only accessible rows are matched
+    let sql_search = "WITH v AS (SELECT uuid FROM lists WHERE owner = ? AND deleted IS NULL
+        UNION SELECT l.uuid FROM lists l JOIN list_permissions p ON p.list = l.uuid
+        WHERE p.user = ? AND l.deleted IS NULL)
+    SELECT h.list,l.name AS list_name,l.name_a,l.name_b,l.owner <> ? AS `foreign`,
+        h.entry,e.tip,h.score
+    FROM (SELECT list,`entry`,MAX(score) AS score FROM (
+        SELECT l.uuid AS list,NULL AS `entry`,MATCH(l.name) AGAINST(? IN BOOLEAN MODE) AS score
+        FROM lists l JOIN v ON v.uuid = l.uuid
+        WHERE ? IS NULL AND MATCH(l.name) AGAINST(? IN BOOLEAN MODE)
+        UNION ALL SELECT e.list,e.uuid,MATCH(e.tip) AGAINST(? IN BOOLEAN MODE)
+        FROM entries e JOIN v ON v.uuid = e.list
+        WHERE ? IS NULL AND MATCH(e.tip) AGAINST(? IN BOOLEAN MODE)
+        UNION ALL SELECT e.list,e.uuid,MATCH(m.value) AGAINST(? IN BOOLEAN MODE)
+        FROM entry_meaning m JOIN entries e ON e.uuid = m.entry JOIN v ON v.uuid = e.list
+        WHERE (? IS NULL OR m.is_a = ?) AND MATCH(m.value) AGAINST(? IN BOOLEAN MODE)
+        ) hits GROUP BY list,`entry`) h
+    JOIN lists l ON l.uuid = h.list
+    LEFT JOIN entries e ON e.uuid = h.entry
+    ORDER BY h.score DESC, h.list, h.entry
+    LIMIT ? OFFSET ?";
+    let hits: Vec<SearchHitBlank> = sqlx::query_as::<_, SearchHitBlank>(sql_search)
+        .bind(user.0)
+        .bind(user.0)
+        .bind(user.0)
+        .bind(&terms)
+        .bind(query.is_a)
+        .bind(&terms)
+        .bind(&terms)
+        .bind(query.is_a)
+        .bind(&terms)
+        .bind(&terms)
+        .bind(query.is_a)
+        .bind(query.is_a)
+        .bind(&terms)
+        .bind(query.limit.min(search::MAX_SEARCH_LIMIT))
+        .bind(query.offset)
+        .fetch(&mut *sql)
+        .try_collect()
+        .await
+        .context("searching")?;
+
+    let sql_meanings = "SELECT value,is_a FROM entry_meaning WHERE entry = ?";
+    let mut results = Vec::with_capacity(hits.len());
+    for hit in hits {
+        let meanings = match hit.entry {
+            Some(entry) => sqlx::query_as::<_, EntryMeaning>(sql_meanings)
+                .bind(entry)
+                .fetch(&mut *sql)
+                .try_collect()
+                .await
+                .context("fetching meanings")?,
+            None => Vec::new(),
+        };
+        results.push(hit.into_full(meanings));
+    }
+    trace!(user=%user,results=results.len(),"searched lists");
+    Ok(results)
+}
+
 /// Check if user has list permission.
-/// TODO: list existing ?
+/// Lists in the trash are treated as not existing.
 pub async fn has_list_perm(
     sql: &mut MySqlConnection,
     user: &UserId,
     list: &ListId,
     perm: Permission,
 ) -> Result<bool> {
-    let sql_owner = "SELECT owner FROM lists WHERE uuid = ?";
+    let sql_owner = "SELECT owner FROM lists WHERE uuid = ? AND deleted IS NULL";
     let owner = match sqlx::query_as::<_, (Uuid,)>(sql_owner)
         .bind(list.0)
         .fetch_optional(&mut *sql)
@@ -607,7 +1603,7 @@ pub async fn has_list_perm(
     }
 
     let sql_foreign = if let Permission::WRITE = perm {
-        "SELECT `change` FROM list_permissions WHERE list = ? AND user = ?"
+        "SELECT `write` FROM list_permissions WHERE list = ? AND user = ?"
     } else if let Permission::READ = perm {
         "SELECT 1 FROM list_permissions WHERE list = ? AND user = ?"
     } else {
@@ -0,0 +1,76 @@
+//! Detection of duplicate entries by their normalized meanings.
+//!
+//! Meanings are normalized only by the generated column entry_meaning.normalized,
+//! lowercase with surrounding and repeated whitespace removed. Values are compared as stored.
+use super::models::*;
+use crate::prelude::*;
+
+/// Meaning with its stored normalized value
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StoredMeaning {
+    pub value: String,
+    pub is_a: bool,
+    pub normalized: String,
+}
+
+impl From<StoredMeaning> for EntryMeaning {
+    fn from(m: StoredMeaning) -> Self {
+        EntryMeaning {
+            value: m.value,
+            is_a: m.is_a,
+        }
+    }
+}
+
+/// Entry with its stored meanings
+#[derive(Debug, Clone)]
+pub struct StoredEntry {
+    pub uuid: Uuid,
+    pub tip: String,
+    pub meanings: Vec<StoredMeaning>,
+}
+
+/// Entries are duplicates if they share a normalized meaning on every side both of them have,
+/// entries without any common side are never duplicates.
+pub fn is_duplicate(a: &[StoredMeaning], b: &[StoredMeaning]) -> bool {
+    fn side(meanings: &[StoredMeaning], is_a: bool) -> Vec<&str> {
+        meanings
+            .iter()
+            .filter(|m| m.is_a == is_a)
+            .map(|m| m.normalized.as_str())
+            .collect()
+    }
+    let mut compared = false;
+    for is_a in [true, false].iter() {
+        let (side_a, side_b) = (side(a, *is_a), side(b, *is_a));
+        if side_a.is_empty() || side_b.is_empty() {
+            continue;
+        }
+        if !side_a.iter().any(|v| side_b.contains(v)) {
+            return false;
+        }
+        compared = true;
+    }
+    compared
+}
+
+/// Merge `other` into `target`, keeping the target tip unless it's empty.
+/// Returns true if the target was changed.
+pub fn merge_entry(target: &mut StoredEntry, other: StoredEntry) -> bool {
+    let mut changed = false;
+    if target.tip.trim().is_empty() && !other.tip.trim().is_empty() {
+        target.tip = other.tip;
+        changed = true;
+    }
+    for m in other.meanings {
+        if !target
+            .meanings
+            .iter()
+            .any(|t| t.is_a == m.is_a && t.normalized == m.normalized)
+        {
+            target.meanings.push(m);
+            changed = true;
+        }
+    }
+    changed
+}
@@ -0,0 +1,207 @@
+//! Export of list entries for other tools.
+//! Entries are formatted while their rows are fetched, the export task holds its pooled
+//! connection until the client received everything.
+use actix_web::web::Bytes;
+use futures::channel::mpsc;
+use futures::{SinkExt, Stream, TryStreamExt};
+use sqlx::pool::PoolConnection;
+use sqlx::{MySql, MySqlConnection};
+
+use super::dao::Permission;
+use super::models::*;
+use super::*;
+
+/// Flush threshold for the output buffer
+const CHUNK_SIZE: usize = 16 * 1024;
+/// Chunks buffered before the export waits for the client
+const CHANNEL_SIZE: usize = 4;
+/// Separator of multiple meanings of one side, understood by the import
+const MEANING_SEPARATOR: &str = "; ";
+
+#[derive(sqlx::FromRow)]
+struct EntryRow {
+    uuid: Uuid,
+    tip: Option<String>,
+    value: Option<String>,
+    is_a: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportEntry {
+    uuid: Uuid,
+    tip: String,
+    a: Vec<String>,
+    b: Vec<String>,
+}
+
+/// Stream the entries of the list, requires read permissions.
+/// Reading and formatting runs in its own task, a failure after the first chunk aborts the response.
+pub async fn export_list(
+    mut conn: PoolConnection<MySql>,
+    user: &UserId,
+    list: ListId,
+    format: ExportFormat,
+) -> Result<impl Stream<Item = Result<Bytes>>> {
+    if !dao::has_list_perm(&mut conn, user, &list, Permission::READ).await? {
+        return Err(ListError::ListPermission);
+    }
+    let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+    actix_rt::spawn(async move {
+        let mut writer = ExportWriter::new(tx, format);
+        if let Err(e) = write_export(&mut conn, &list, &mut writer).await {
+            warn!(%list, ?e, "list export failed");
+            let _ = writer.tx.send(Err(e)).await;
+        }
+    });
+    Ok(rx)
+}
+
+/// Write all entries of the list with their meanings, oldest first
+async fn write_export(
+    sql: &mut MySqlConnection,
+    list: &ListId,
+    w: &mut ExportWriter,
+) -> Result<()> {
+    let sql_entries = "SELECT e.uuid,e.tip,m.value,m.is_a FROM entries e
+        LEFT JOIN entry_meaning m ON m.entry = e.uuid
+        WHERE e.list = ? ORDER BY e.changed,e.uuid";
+    let mut rows = sqlx::query_as::<_, EntryRow>(sql_entries)
+        .bind(list.0)
+        .fetch(sql);
+    let mut entries = 0;
+    let mut entry: Option<ExportEntry> = None;
+    w.begin();
+    while let Some(row) = rows.try_next().await? {
+        // rows are ordered by entry, meanings of an entry are adjacent
+        if entry.as_ref().map(|e| e.uuid) != Some(row.uuid) {
+            let next = ExportEntry {
+                uuid: row.uuid,
+                tip: row.tip.unwrap_or_default(),
+                a: Vec::new(),
+                b: Vec::new(),
+            };
+            if let Some(done) = entry.replace(next) {
+                w.entry(&done).await?;
+                entries += 1;
+            }
+        }
+        if let (Some(value), Some(is_a), Some(entry)) = (row.value, row.is_a, entry.as_mut()) {
+            match is_a {
+                true => entry.a.push(value),
+                false => entry.b.push(value),
+            }
+        }
+    }
+    if let Some(done) = entry {
+        w.entry(&done).await?;
+        entries += 1;
+    }
+    w.end();
+    w.flush().await?;
+    trace!(%list, entries, "exported list");
+    Ok(())
+}
+
+/// Formats entries and sends them in chunks
+struct ExportWriter {
+    tx: mpsc::Sender<Result<Bytes>>,
+    format: ExportFormat,
+    buffer: String,
+    first: bool,
+}
+
+impl ExportWriter {
+    fn new(tx: mpsc::Sender<Result<Bytes>>, format: ExportFormat) -> Self {
+        Self {
+            tx,
+            format,
+            buffer: String::with_capacity(CHUNK_SIZE),
+            first: true,
+        }
+    }
+
+    fn begin(&mut self) {
+        match self.format {
+            ExportFormat::Csv => self.buffer.push_str("a,b,tip\r\n"),
+            ExportFormat::Tsv => self.buffer.push_str("a\tb\ttip\r\n"),
+            ExportFormat::Json => self.buffer.push('['),
+            ExportFormat::AnkiTxt => self.buffer.push_str("#separator:tab\n#html:false\n"),
+        }
+    }
+
+    fn end(&mut self) {
+        if self.format == ExportFormat::Json {
+            self.buffer.push(']');
+        }
+    }
+
+    async fn entry(&mut self, entry: &ExportEntry) -> Result<()> {
+        let a = entry.a.join(MEANING_SEPARATOR);
+        let b = entry.b.join(MEANING_SEPARATOR);
+        match self.format {
+            ExportFormat::Csv | ExportFormat::Tsv => {
+                let delimiter = match self.format {
+                    ExportFormat::Csv => ',',
+                    _ => '\t',
+                };
+                for (i, field) in [&a, &b, &entry.tip].iter().enumerate() {
+                    if i > 0 {
+                        self.buffer.push(delimiter);
+                    }
+                    push_quoted(&mut self.buffer, field, delimiter);
+                }
+                self.buffer.push_str("\r\n");
+            }
+            ExportFormat::Json => {
+                if !self.first {
+                    self.buffer.push(',');
+                }
+                self.buffer.push_str(&serde_json::to_string(entry)?);
+            }
+            ExportFormat::AnkiTxt => {
+                for (i, field) in [&a, &b, &entry.tip].iter().enumerate() {
+                    if i > 0 {
+                        self.buffer.push('\t');
+                    }
+                    // no quoting in plain text notes
+                    self.buffer.extend(field.chars().map(|c| match c {
+                        '\t' | '\r' | '\n' => ' ',
+                        c => c,
+                    }));
+                }
+                self.buffer.push('\n');
+            }
+        }
+        self.first = false;
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(
+            &mut self.buffer,
+            String::with_capacity(CHUNK_SIZE),
+        ));
+        self.tx
+            .send(Ok(chunk))
+            .await
+            .context("export receiver dropped")?;
+        Ok(())
+    }
+}
+
+/// Quote the field if required, doubling contained quotes
+fn push_quoted(buffer: &mut String, field: &str, delimiter: char) {
+    if field.contains(|c| c == delimiter || c == '"' || c == '\n' || c == '\r') {
+        buffer.push('"');
+        buffer.push_str(&field.replace('"', "\"\""));
+        buffer.push('"');
+    } else {
+        buffer.push_str(field);
+    }
+}
@@ -0,0 +1,74 @@
+//! Revisions of lists and entries, recorded with the previous values before every change.
+//! Creations, copies and moves are recorded afterwards with the new values.
+//!
+//! Used by the REST routes and sync, see [super::dao::history] for retrieval.
+use chrono::Utc;
+use sqlx::MySqlConnection;
+use strum::AsRefStr;
+
+use crate::prelude::*;
+
+/// Maximum revisions returned per history request
+pub const MAX_HISTORY_LIMIT: u32 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum Action {
+    Create,
+    Change,
+    Move,
+    Delete,
+    Revert,
+}
+
+/// Store the current names of the list, before they are changed by `user` or after creating it
+pub async fn record_list(
+    sql: &mut MySqlConnection,
+    list: &Uuid,
+    user: &UserId,
+    action: Action,
+) -> sqlx::Result<()> {
+    let sql_insert = "INSERT INTO revision (list,user,action,created,name,name_a,name_b)
+        SELECT uuid,?,?,?,name,name_a,name_b FROM lists WHERE uuid = ?";
+    let res = sqlx::query(sql_insert)
+        .bind(user.0)
+        .bind(action.as_ref())
+        .bind(Utc::now().naive_utc())
+        .bind(list)
+        .execute(sql)
+        .await?;
+    trace!(%list, affected = res.rows_affected(), "recorded list revision");
+    Ok(())
+}
+
+/// Store the current tip and meanings of the entry, before it's changed or deleted by `user`
+/// or after creating or moving it.
+/// Does nothing for unknown entries.
+pub async fn record_entry(
+    sql: &mut MySqlConnection,
+    entry: &Uuid,
+    user: &UserId,
+    action: Action,
+) -> sqlx::Result<()> {
+    let sql_insert = "INSERT INTO revision (list,`entry`,user,action,created,tip)
+        SELECT list,uuid,?,?,?,tip FROM entries WHERE uuid = ?";
+    let res = sqlx::query(sql_insert)
+        .bind(user.0)
+        .bind(action.as_ref())
+        .bind(Utc::now().naive_utc())
+        .bind(entry)
+        .execute(&mut *sql)
+        .await?;
+    trace!(%entry, affected = res.rows_affected(), "recorded entry revision");
+    if res.rows_affected() == 0 {
+        return Ok(());
+    }
+    let sql_meanings = "INSERT INTO revision_meaning (revision,value,is_a)
+        SELECT ?,value,is_a FROM entry_meaning WHERE entry = ?";
+    sqlx::query(sql_meanings)
+        .bind(res.last_insert_id())
+        .bind(entry)
+        .execute(sql)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! Parsing of CSV/TSV vocabulary imports, inserted by [super::dao::import_entries].
+use super::models::*;
+use super::*;
+
+/// Maximum length of list names, see lists.name
+pub const MAX_LIST_NAME_LENGTH: usize = 127;
+/// Maximum length of meanings, see entry_meaning.value
+pub const MAX_MEANING_LENGTH: usize = 120;
+/// Maximum length of tips, see entries.tip
+pub const MAX_TIP_LENGTH: usize = 127;
+/// Maximum data rows per import
+pub const MAX_IMPORT_ROWS: usize = 5000;
+
+/// Parse import data into entries, invalid rows are reported with their 1 based row number.
+pub fn parse(data: &str, opts: &ImportOptions) -> Result<(Vec<EntryCreate>, Vec<ImportRowError>)> {
+    let data = data.strip_prefix('\u{feff}').unwrap_or(data);
+    let records = records(data, opts.format.delimiter());
+    let skip = usize::from(opts.header);
+    if records.len().saturating_sub(skip) > MAX_IMPORT_ROWS {
+        return Err(ListError::ValidationError("rows"));
+    }
+
+    let mut entries = Vec::with_capacity(records.len());
+    let mut errors = Vec::new();
+    for (i, fields) in records.iter().enumerate().skip(skip) {
+        // ignore blank lines
+        if fields.iter().all(|v| v.trim().is_empty()) {
+            continue;
+        }
+        match parse_row(fields, opts) {
+            Ok(entry) => entries.push(entry),
+            Err(error) => errors.push(ImportRowError { row: i + 1, error }),
+        }
+    }
+    Ok((entries, errors))
+}
+
+fn parse_row(fields: &[String], opts: &ImportOptions) -> std::result::Result<EntryCreate, String> {
+    let column = |col: usize| {
+        fields
+            .get(col)
+            .map(|v| v.trim())
+            .ok_or_else(|| format!("missing column {}", col))
+    };
+    let mut meanings = split_meanings(column(opts.col_a)?, &opts.separator, true);
+    if meanings.is_empty() {
+        return Err("no meaning for side A".to_owned());
+    }
+    let meanings_b = split_meanings(column(opts.col_b)?, &opts.separator, false);
+    if meanings_b.is_empty() {
+        return Err("no meaning for side B".to_owned());
+    }
+    meanings.extend(meanings_b);
+
+    let tip = match opts.col_tip {
+        Some(col) => column(col)?.to_owned(),
+        None => String::new(),
+    };
+    validate_entry(&tip, &meanings)?;
+    Ok(EntryCreate { tip, meanings })
+}
+
+/// Check lengths against entries.tip and entry_meaning.value
+pub fn validate_entry(tip: &str, meanings: &[EntryMeaning]) -> std::result::Result<(), String> {
+    if let Some(m) = meanings
+        .iter()
+        .find(|m| m.value.chars().count() > MAX_MEANING_LENGTH)
+    {
+        return Err(format!(
+            "meaning '{}' longer than {} characters",
+            m.value, MAX_MEANING_LENGTH
+        ));
+    }
+    if tip.chars().count() > MAX_TIP_LENGTH {
+        return Err(format!("tip longer than {} characters", MAX_TIP_LENGTH));
+    }
+    Ok(())
+}
+
+/// Split a cell into its meanings
+fn split_meanings(cell: &str, separator: &str, is_a: bool) -> Vec<EntryMeaning> {
+    let values: Vec<&str> = if separator.is_empty() {
+        vec![cell]
+    } else {
+        cell.split(separator).collect()
+    };
+    values
+        .into_iter()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|value| EntryMeaning {
+            value: value.to_owned(),
+            is_a,
+        })
+        .collect()
+}
+
+/// Split into records of fields. Fields can be quoted with `"`, containing
+/// delimiters, line breaks and `""` as escaped quote.
+fn records(data: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
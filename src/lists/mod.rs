@@ -2,11 +2,19 @@ use crate::prelude::*;
 use actix_web::{HttpResponse, ResponseError};
 use thiserror::Error;
 
+mod backup;
 mod dao;
+mod dedup;
+mod export;
+pub mod history;
+mod import;
 mod models;
 pub mod routes;
+mod search;
+pub mod sorting;
 #[cfg(test)]
 mod tests;
+pub mod trash;
 
 #[derive(Error, Debug)]
 pub enum ListError {
@@ -18,12 +26,14 @@ pub enum ListError {
     Serde(#[from] serde_json::error::Error),
     #[error("db error")]
     Sqlx(#[from] sqlx::Error),
-    #[error("invalid or missing auth")]
-    NotAuthenticated,
     #[error("missing permission for list")]
     ListPermission,
     #[error("list not existing")]
     ListNotFound,
+    #[error("revision not existing")]
+    RevisionNotFound,
+    #[error("duplicate of entry {0}")]
+    DuplicateEntry(Uuid),
     #[error("sharecode invalid")]
     SharecodeInvalid,
     #[error("sharecode outdated")]
@@ -40,11 +50,19 @@ impl ResponseError for ListError {
                 .reason("invalid payload")
                 .finish(),
             ListError::ListNotFound => HttpResponse::NotFound().reason("invalid list").finish(),
+            ListError::RevisionNotFound => {
+                HttpResponse::NotFound().reason("invalid revision").finish()
+            }
+            ListError::DuplicateEntry(entry) => HttpResponse::Conflict()
+                .reason("duplicate entry")
+                .json(serde_json::json!({ "entry": entry })),
             ListError::SharecodeInvalid => HttpResponse::NotFound().reason("invalid").finish(),
+            ListError::ValidationError(field) => HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(format!("invalid {}", field)),
             ListError::ListPermission => HttpResponse::Forbidden()
                 .reason("missing permissions for list")
                 .finish(),
-            ListError::NotAuthenticated => HttpResponse::Unauthorized().finish(),
             e => {
                 error!("{}", e);
                 HttpResponse::InternalServerError().finish()
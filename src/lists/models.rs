@@ -10,6 +10,17 @@ pub struct List {
     pub change: bool,
 }
 
+/// A deleted list, restorable until `purge_at`
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TrashedList {
+    pub uuid: Uuid,
+    pub name: String,
+    pub name_a: String,
+    pub name_b: String,
+    pub deleted: Timestamp,
+    pub purge_at: Timestamp,
+}
+
 /// A user with which a list is shared
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct SharedUser {
@@ -49,8 +60,8 @@ pub struct ShareTokenEntry {
     pub reusable: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
-#[cfg_attr(test, derive(PartialEq, Clone))]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[cfg_attr(test, derive(PartialEq))]
 pub struct EntryMeaning {
     pub value: String,
     pub is_a: bool,
@@ -65,6 +76,99 @@ pub struct ListChange {
 
 pub type ListCreate = ListChange;
 
+/// Copy of a readable list, owned by the caller
+#[derive(Debug, Deserialize)]
+pub struct ListCopy {
+    /// Defaults to the name of the original
+    pub name: Option<String>,
+    /// Record the original list and entries as source
+    #[serde(default)]
+    pub track_source: bool,
+}
+
+/// Merge of another list into the target list
+#[derive(Debug, Deserialize)]
+pub struct ListMerge {
+    /// List merged into the target, deleted afterwards
+    pub source: Uuid,
+    /// Copy the entries and keep the source list
+    #[serde(default)]
+    pub copy: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MergeReport {
+    /// Entries moved or copied into the target list
+    pub entries: usize,
+    /// Entries merged into an existing duplicate
+    pub merged: usize,
+}
+
+/// Order of paginated entries, `a`/`b` sort by the first meaning of that side
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntrySort {
+    Created,
+    Changed,
+    A,
+    B,
+}
+
+impl Default for EntrySort {
+    fn default() -> Self {
+        EntrySort::Changed
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EntriesQuery {
+    #[serde(default)]
+    pub sort: EntrySort,
+    #[serde(default)]
+    pub desc: bool,
+    /// `next` cursor of the previous page, only valid for the same sorting
+    pub after: Option<String>,
+    pub changed_from: Option<Timestamp>,
+    pub changed_to: Option<Timestamp>,
+    #[serde(default = "EntriesQuery::default_limit")]
+    pub limit: u32,
+}
+
+impl EntriesQuery {
+    fn default_limit() -> u32 {
+        100
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntryPage {
+    pub entries: Vec<Entry>,
+    /// Cursor for the next page, not set on the last page
+    pub next: Option<String>,
+}
+
+/// Position after the last entry of a page, sort key and entry as tie breaker
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EntryCursor {
+    pub key: String,
+    pub entry: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateEntryQuery {
+    /// Return the existing entry instead of inserting a duplicate
+    #[serde(default)]
+    pub reject_duplicates: bool,
+}
+
+/// Entries of a list sharing a normalized meaning
+#[derive(Debug, Serialize)]
+pub struct Duplicate {
+    pub value: String,
+    pub is_a: bool,
+    pub entries: Vec<Uuid>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[cfg_attr(test, derive(Clone))]
 pub struct EntryChange {
@@ -77,6 +181,257 @@ pub struct Entry {
     pub tip: String,
     pub uuid: Uuid,
     pub meanings: Vec<EntryMeaning>,
+    pub created: Timestamp,
+    /// Not set for entries written before authors were recorded
+    pub created_by: Option<Uuid>,
+    pub changed_by: Option<Uuid>,
 }
 
 pub type EntryCreate = EntryChange;
+
+/// Previous values of a list or entry, `entry` is set for entry revisions
+#[derive(Debug, Serialize)]
+pub struct Revision {
+    pub id: u64,
+    pub entry: Option<Uuid>,
+    /// Author of the change
+    pub user: Uuid,
+    /// Not set for deleted accounts
+    pub user_name: Option<String>,
+    pub action: String,
+    pub created: Timestamp,
+    pub name: Option<String>,
+    pub name_a: Option<String>,
+    pub name_b: Option<String>,
+    pub tip: Option<String>,
+    pub meanings: Vec<EntryMeaning>,
+}
+
+// raw revision from DB without meanings
+#[derive(Debug, sqlx::FromRow)]
+pub struct RevisionBlank {
+    pub id: u64,
+    pub entry: Option<Uuid>,
+    pub user: Uuid,
+    pub user_name: Option<String>,
+    pub action: String,
+    pub created: Timestamp,
+    pub name: Option<String>,
+    pub name_a: Option<String>,
+    pub name_b: Option<String>,
+    pub tip: Option<String>,
+}
+
+impl RevisionBlank {
+    pub fn into_full(self, meanings: Vec<EntryMeaning>) -> Revision {
+        Revision {
+            id: self.id,
+            entry: self.entry,
+            user: self.user,
+            user_name: self.user_name,
+            action: self.action,
+            created: self.created,
+            name: self.name,
+            name_a: self.name_a,
+            name_b: self.name_b,
+            tip: self.tip,
+            meanings,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// continue after this revision id
+    pub before: Option<u64>,
+    #[serde(default = "HistoryQuery::default_limit")]
+    pub limit: u32,
+}
+
+impl HistoryQuery {
+    fn default_limit() -> u32 {
+        50
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// Only match meanings of this side, excludes list names and tips
+    pub is_a: Option<bool>,
+    #[serde(default)]
+    pub offset: u32,
+    #[serde(default = "SearchQuery::default_limit")]
+    pub limit: u32,
+}
+
+impl SearchQuery {
+    fn default_limit() -> u32 {
+        20
+    }
+}
+
+/// Search result, `entry` is set for matching entries
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub list: Uuid,
+    pub list_name: String,
+    pub name_a: String,
+    pub name_b: String,
+    pub foreign: bool,
+    pub entry: Option<Uuid>,
+    pub tip: Option<String>,
+    pub meanings: Vec<EntryMeaning>,
+    pub score: f32,
+}
+
+// raw search result from DB without meanings
+#[derive(Debug, sqlx::FromRow)]
+pub struct SearchHitBlank {
+    pub list: Uuid,
+    pub list_name: String,
+    pub name_a: String,
+    pub name_b: String,
+    pub foreign: bool,
+    pub entry: Option<Uuid>,
+    pub tip: Option<String>,
+    pub score: f32,
+}
+
+impl SearchHitBlank {
+    pub fn into_full(self, meanings: Vec<EntryMeaning>) -> SearchHit {
+        SearchHit {
+            list: self.list,
+            list_name: self.list_name,
+            name_a: self.name_a,
+            name_b: self.name_b,
+            foreign: self.foreign,
+            entry: self.entry,
+            tip: self.tip,
+            meanings,
+            score: self.score,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Csv,
+    Tsv,
+}
+
+impl ImportFormat {
+    pub fn delimiter(self) -> char {
+        match self {
+            ImportFormat::Csv => ',',
+            ImportFormat::Tsv => '\t',
+        }
+    }
+}
+
+/// Column mapping of an import, columns are 0 based
+#[derive(Debug, Deserialize)]
+pub struct ImportOptions {
+    #[serde(default = "ImportOptions::default_format")]
+    pub format: ImportFormat,
+    /// Meanings of side A
+    pub col_a: usize,
+    /// Meanings of side B
+    pub col_b: usize,
+    pub col_tip: Option<usize>,
+    /// Separator of multiple meanings in one column, empty for none
+    #[serde(default = "ImportOptions::default_separator")]
+    pub separator: String,
+    /// Skip the first row
+    #[serde(default)]
+    pub header: bool,
+    /// Only validate and return the parsed entries
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl ImportOptions {
+    fn default_format() -> ImportFormat {
+        ImportFormat::Csv
+    }
+
+    fn default_separator() -> String {
+        ";".to_owned()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportRowError {
+    /// 1 based, including the header
+    pub row: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    /// Nothing was inserted, set for dry runs and imports with invalid rows
+    pub dry_run: bool,
+    /// Valid rows
+    pub rows: usize,
+    pub imported: usize,
+    pub errors: Vec<ImportRowError>,
+    /// Parsed entries of a dry run
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub preview: Vec<EntryCreate>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    Csv,
+    Tsv,
+    Json,
+    /// Anki plain text notes
+    AnkiTxt,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Tsv => "text/tab-separated-values; charset=utf-8",
+            ExportFormat::Json => "application/json",
+            ExportFormat::AnkiTxt => "text/plain; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Tsv => "tsv",
+            ExportFormat::Json => "json",
+            ExportFormat::AnkiTxt => "txt",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: ExportFormat,
+}
+
+/// Entry of a backup which couldn't be imported
+#[derive(Debug, Serialize)]
+pub struct BackupEntryError {
+    pub list: Uuid,
+    /// Local id in the app
+    pub entry: i64,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BackupImportReport {
+    pub lists: Vec<Uuid>,
+    /// Lists existing already or deleted
+    pub skipped_lists: Vec<Uuid>,
+    pub entries: usize,
+    pub skipped_entries: usize,
+    pub categories: usize,
+    pub errors: Vec<BackupEntryError>,
+}
@@ -1,10 +1,10 @@
 use super::models::*;
 use super::*;
-use actix_identity::Identity;
-use actix_web::{delete, get, post, put, web, HttpResponse};
+use actix_web::{delete, get, http::header, post, put, web, HttpResponse};
 
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(all_lists)
+        .service(trash)
         .service(single_list)
         .service(list_sharing_info)
         .service(list_sharing_use)
@@ -13,29 +13,53 @@ pub fn init(cfg: &mut web::ServiceConfig) {
         .service(list_sharing_change_perms)
         .service(change_list)
         .service(delete_list)
+        .service(restore_list)
         .service(create_list)
+        .service(copy_list)
+        .service(merge_lists)
         .service(delete_entry)
         .service(list_entries)
         .service(change_entry)
-        .service(create_entry);
+        .service(create_entry)
+        .service(list_duplicates)
+        .service(import_entries)
+        .service(import_backup)
+        .service(export_entries)
+        .service(list_history)
+        .service(revert_revision)
+        .service(search);
 }
 
 // #[instrument(skip(id,reg,state))]
 #[get("/api/v1/lists")]
-async fn all_lists(id: Identity, state: AppState) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+async fn all_lists(user: AuthUser, state: AppState) -> Result<HttpResponse> {
+    let user = user.0;
 
     let response = dao::all_lists(&mut *state.sql.acquire().await?, &user).await?;
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Deleted lists of the user, registered before `{list}` routes
+#[get("/api/v1/lists/trash")]
+async fn trash(user: AuthUser, state: AppState) -> Result<HttpResponse> {
+    let user = user.0;
+
+    let response = dao::trash(
+        &mut *state.sql.acquire().await?,
+        &user,
+        state.config.trash.retention,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
 #[get("/api/v1/lists/{list}")]
 async fn single_list(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid,)>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let (list,) = path.into_inner();
 
     let response = dao::single_list(&mut *state.sql.acquire().await?, &user, &ListId(list)).await?;
@@ -45,11 +69,11 @@ async fn single_list(
 /// List sharing data, owner only
 #[get("/api/v1/lists/{list}/sharing")]
 async fn list_sharing_info(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid,)>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let (list,) = path.into_inner();
 
     let response =
@@ -60,11 +84,11 @@ async fn list_sharing_info(
 /// List sharing data, owner only
 #[delete("/api/v1/lists/{list}/sharing/{user}")]
 async fn list_sharing_remove_user(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid, Uuid)>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let (list, shared_user) = path.into_inner();
 
     dao::remove_sharing_user(
@@ -80,12 +104,12 @@ async fn list_sharing_remove_user(
 /// Update user permissions
 #[put("/api/v1/lists/{list}/sharing/{user}")]
 async fn list_sharing_change_perms(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid, Uuid)>,
     reg: web::Json<UserPermissions>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let (list, shared_user) = path.into_inner();
     let perms = reg.into_inner();
 
@@ -103,12 +127,12 @@ async fn list_sharing_change_perms(
 /// Create auth code for sharing
 #[post("/api/v1/lists/{list}/share")]
 async fn list_sharing_add(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid,)>,
     reg: web::Json<NewTokenData>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let (list,) = path.into_inner();
     let perms = reg.into_inner();
 
@@ -125,11 +149,11 @@ async fn list_sharing_add(
 /// Use auth code for list sharing
 #[post("/api/v1/lists/share/{code}/{secret}")]
 async fn list_sharing_use(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(String, String)>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let (code, secret) = path.into_inner();
 
     dao::use_share_code(&mut *state.sql.acquire().await?, &user, &code, &secret).await?;
@@ -138,25 +162,39 @@ async fn list_sharing_use(
 
 #[delete("/api/v1/lists/{list}")]
 async fn delete_list(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid,)>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let (list,) = path.into_inner();
 
     let response = dao::delete_list(&mut *state.sql.acquire().await?, &user, ListId(list)).await?;
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Restore a list from the trash, owner only
+#[post("/api/v1/lists/{list}/restore")]
+async fn restore_list(
+    user: AuthUser,
+    state: AppState,
+    path: web::Path<(Uuid,)>,
+) -> Result<HttpResponse> {
+    let user = user.0;
+    let (list,) = path.into_inner();
+
+    dao::restore_list(&mut *state.sql.acquire().await?, &user, ListId(list)).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
 #[post("/api/v1/lists/{list}")]
 async fn change_list(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid,)>,
     reg: web::Json<ListChange>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let (list,) = path.into_inner();
     let data = reg.into_inner();
 
@@ -167,37 +205,79 @@ async fn change_list(
 
 #[post("/api/v1/lists")]
 async fn create_list(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     reg: web::Json<ListCreate>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let data = reg.into_inner();
 
     let response = dao::create_list(&mut *state.sql.acquire().await?, &user, data).await?;
     Ok(HttpResponse::Ok().json(response.0))
 }
 
+/// Copy a readable list into a new list owned by the user
+#[post("/api/v1/lists/{list}/copy")]
+async fn copy_list(
+    user: AuthUser,
+    state: AppState,
+    path: web::Path<(Uuid,)>,
+    reg: web::Json<ListCopy>,
+) -> Result<HttpResponse> {
+    let user = user.0;
+    let (list,) = path.into_inner();
+    let data = reg.into_inner();
+
+    let response =
+        dao::copy_list(&mut *state.sql.acquire().await?, &user, &ListId(list), data).await?;
+    Ok(HttpResponse::Ok().json(response.0))
+}
+
+/// Merge another list into this one, owner only
+#[post("/api/v1/lists/{list}/merge")]
+async fn merge_lists(
+    user: AuthUser,
+    state: AppState,
+    path: web::Path<(Uuid,)>,
+    reg: web::Json<ListMerge>,
+) -> Result<HttpResponse> {
+    let user = user.0;
+    let (list,) = path.into_inner();
+    let data = reg.into_inner();
+
+    let response =
+        dao::merge_lists(&mut *state.sql.acquire().await?, &user, &ListId(list), data).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Entries of the list, ordered and paginated
 #[get("/api/v1/lists/{list}/entries")]
 async fn list_entries(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid,)>,
+    query: web::Query<EntriesQuery>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let (list,) = path.into_inner();
 
-    let response = dao::entries(&mut *state.sql.acquire().await?, &user, ListId(list)).await?;
+    let response = dao::entry_page(
+        &mut *state.sql.acquire().await?,
+        &user,
+        &ListId(list),
+        query.into_inner(),
+    )
+    .await?;
     Ok(HttpResponse::Ok().json(response))
 }
 
 #[delete("/api/v1/lists/{list}/entry/{entry}")]
 async fn delete_entry(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid, Uuid)>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     // TODO: we don't need the list, we have to resolve the entry-list by ourself anyway
     // but its logical to have this API path
     let (_list, entry) = path.into_inner();
@@ -209,12 +289,12 @@ async fn delete_entry(
 
 #[post("/api/v1/lists/{list}/entry/{entry}")]
 async fn change_entry(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid, Uuid)>,
-    data: web::Path<EntryChange>,
+    data: web::Json<EntryChange>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     // TODO: we don't need the list, we have to resolve the entry-list by ourself anyway
     // but its logical to have this API path
     let (_list, entry) = path.into_inner();
@@ -232,23 +312,178 @@ async fn change_entry(
 
 #[post("/api/v1/lists/{list}/entry")]
 async fn create_entry(
-    id: Identity,
+    user: AuthUser,
     state: AppState,
     path: web::Path<(Uuid,)>,
-    data: web::Path<EntryCreate>,
+    query: web::Query<CreateEntryQuery>,
+    data: web::Json<EntryCreate>,
 ) -> Result<HttpResponse> {
-    let user = get_user(id)?;
+    let user = user.0;
     let (list,) = path.into_inner();
     let data = data.into_inner();
 
-    let response =
-        dao::create_entry(&mut *state.sql.acquire().await?, user, ListId(list), data).await?;
+    let response = dao::create_entry(
+        &mut *state.sql.acquire().await?,
+        user,
+        ListId(list),
+        data,
+        query.reject_duplicates,
+    )
+    .await?;
     Ok(HttpResponse::Ok().json(response.0))
 }
 
-/// Retrieve user from IDentity or error out
-fn get_user(id: actix_identity::Identity) -> Result<UserId> {
-    Ok(UserId(Uuid::parse_str(
-        &id.identity().ok_or(ListError::NotAuthenticated)?,
-    )?))
+/// Meanings used by several entries of the list
+#[get("/api/v1/lists/{list}/duplicates")]
+async fn list_duplicates(
+    user: AuthUser,
+    state: AppState,
+    path: web::Path<(Uuid,)>,
+) -> Result<HttpResponse> {
+    let user = user.0;
+    let (list,) = path.into_inner();
+
+    let response = dao::duplicates(&mut *state.sql.acquire().await?, &user, &ListId(list)).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Import entries from CSV/TSV, the column mapping is passed as query.
+/// Nothing is inserted if any row is invalid.
+#[post("/api/v1/lists/{list}/import")]
+async fn import_entries(
+    user: AuthUser,
+    state: AppState,
+    path: web::Path<(Uuid,)>,
+    query: web::Query<ImportOptions>,
+    body: String,
+) -> Result<HttpResponse> {
+    let user = user.0;
+    let (list,) = path.into_inner();
+    let opts = query.into_inner();
+
+    let (entries, errors) = import::parse(&body, &opts)?;
+    let dry_run = opts.dry_run || !errors.is_empty();
+    let rows = entries.len();
+    let (imported, preview) = if dry_run {
+        dao::import_entries(
+            &mut *state.sql.acquire().await?,
+            &user,
+            &ListId(list),
+            Vec::new(),
+            true,
+        )
+        .await?;
+        (0, entries)
+    } else {
+        let imported = dao::import_entries(
+            &mut *state.sql.acquire().await?,
+            &user,
+            &ListId(list),
+            entries,
+            false,
+        )
+        .await?;
+        (imported, Vec::new())
+    };
+    Ok(HttpResponse::Ok().json(ImportReport {
+        dry_run,
+        rows,
+        imported,
+        errors,
+        preview,
+    }))
+}
+
+/// Import lists of a VocableTrainer Android database, sent as request body
+#[post("/api/v1/lists/import/vocabletrainer")]
+async fn import_backup(
+    user: AuthUser,
+    state: AppState,
+    body: web::Payload,
+) -> Result<HttpResponse> {
+    let user = user.0;
+
+    let data = backup::read_body(body).await?;
+    let backup = backup::read_backup(data, &user).await?;
+    let response = dao::import_backup(&mut *state.sql.acquire().await?, &user, backup).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Download all entries, for users with read permission
+#[get("/api/v1/lists/{list}/export")]
+async fn export_entries(
+    user: AuthUser,
+    state: AppState,
+    path: web::Path<(Uuid,)>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse> {
+    let user = user.0;
+    let (list,) = path.into_inner();
+    let format = query.into_inner().format;
+
+    let stream =
+        export::export_list(state.sql.acquire().await?, &user, ListId(list), format).await?;
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.{}\"", list, format.extension()),
+        ))
+        .streaming(stream))
+}
+
+/// Revisions of the list and its entries, newest first
+#[get("/api/v1/lists/{list}/history")]
+async fn list_history(
+    user: AuthUser,
+    state: AppState,
+    path: web::Path<(Uuid,)>,
+    query: web::Query<HistoryQuery>,
+) -> Result<HttpResponse> {
+    let user = user.0;
+    let (list,) = path.into_inner();
+    let query = query.into_inner();
+
+    let response = dao::history(
+        &mut *state.sql.acquire().await?,
+        &user,
+        &ListId(list),
+        query.before,
+        query.limit,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Restore the values of a revision, returns the entry for entry revisions
+#[post("/api/v1/lists/{list}/history/{revision}/revert")]
+async fn revert_revision(
+    user: AuthUser,
+    state: AppState,
+    path: web::Path<(Uuid, u64)>,
+) -> Result<HttpResponse> {
+    let user = user.0;
+    let (list, revision) = path.into_inner();
+
+    let response = dao::revert_revision(
+        &mut *state.sql.acquire().await?,
+        &user,
+        ListId(list),
+        revision,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(response.map(|v| v.0)))
+}
+
+/// Search all lists of the user including shared ones
+#[get("/api/v1/search")]
+async fn search(
+    user: AuthUser,
+    state: AppState,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse> {
+    let user = user.0;
+
+    let response = dao::search(&mut *state.sql.acquire().await?, &user, query.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(response))
 }
@@ -0,0 +1,25 @@
+//! Full text search over all lists accessible by a user, see [super::dao::search].
+//!
+//! Every word of the query has to match as prefix of a word, via MATCH AGAINST in boolean mode.
+//! Which words are indexed depends on the server settings, see the search migration.
+
+/// Maximum results returned per search request
+pub const MAX_SEARCH_LIMIT: u32 = 100;
+/// Maximum length of search queries
+pub const MAX_QUERY_LENGTH: usize = 200;
+
+/// Convert user input into a boolean mode query requiring every word as prefix.
+/// Returns None if nothing searchable is left.
+pub fn boolean_query(q: &str) -> Option<String> {
+    // operators and punctuation are word separators for the full text parser anyway
+    let words: Vec<String> = q
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("+{}*", w))
+        .collect();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
@@ -0,0 +1,17 @@
+//! Sort keys of entries, the smallest meaning of each side stored in `entries.sort_a/sort_b`.
+//!
+//! Has to be called after every change of meanings, by the REST routes and sync.
+use sqlx::MySqlConnection;
+
+use crate::prelude::*;
+
+/// Recalculate the sort keys of the entry from its current meanings
+pub async fn update_sort_keys(sql: &mut MySqlConnection, entry: &Uuid) -> sqlx::Result<()> {
+    let sql_update = "UPDATE entries e SET
+        sort_a = IFNULL((SELECT MIN(m.value) FROM entry_meaning m WHERE m.entry = e.uuid AND m.is_a),''),
+        sort_b = IFNULL((SELECT MIN(m.value) FROM entry_meaning m WHERE m.entry = e.uuid AND NOT m.is_a),'')
+        WHERE e.uuid = ?";
+    let res = sqlx::query(sql_update).bind(entry).execute(sql).await?;
+    trace!(%entry, affected = res.rows_affected(), "updated sort keys");
+    Ok(())
+}
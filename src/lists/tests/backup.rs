@@ -0,0 +1,166 @@
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection, Executor};
+
+use crate::lists::backup::*;
+
+use super::*;
+
+const LIST_UUID: &str = "3e3ad7bf-7ebc-4b53-9bc8-2d5c0be6e2a2";
+
+/// Create an app database with two lists, returns the file content
+async fn gen_backup() -> Vec<u8> {
+    gen_backup_with("").await
+}
+
+/// [gen_backup] with `extra` SQL executed afterwards
+async fn gen_backup_with(extra: &str) -> Vec<u8> {
+    let path = std::env::temp_dir().join(format!("vta-test-{}.db", Uuid::new_v4()));
+    let mut conn = SqliteConnectOptions::new()
+        .filename(&path)
+        .create_if_missing(true)
+        .connect()
+        .await
+        .unwrap();
+    conn.execute(
+        "CREATE TABLE list (list INTEGER PRIMARY KEY, list_name TEXT NOT NULL, name_a TEXT NOT NULL,
+            name_b TEXT NOT NULL, created INTEGER, changed INTEGER, uuid TEXT);
+        CREATE TABLE vocables (voc INTEGER PRIMARY KEY, list INTEGER NOT NULL, tip TEXT,
+            addition TEXT, created INTEGER, changed INTEGER);
+        CREATE TABLE meaning_a (voc INTEGER NOT NULL, meaning TEXT NOT NULL);
+        CREATE TABLE meaning_b (voc INTEGER NOT NULL, meaning TEXT NOT NULL);
+        CREATE TABLE category (category INTEGER PRIMARY KEY, category_name TEXT NOT NULL);
+        CREATE TABLE list_categories (list INTEGER NOT NULL, category INTEGER NOT NULL);
+        INSERT INTO list VALUES (1, 'animals', 'en', 'de', 1500000000000, 1600000000000, NULL);
+        INSERT INTO list VALUES (2, 'food', 'en', 'de', 1500000000000, 99999999999999,
+            '3e3ad7bf-7ebc-4b53-9bc8-2d5c0be6e2a2');
+        INSERT INTO vocables VALUES (1, 1, 'pet', NULL, NULL, 1600000000000);
+        INSERT INTO vocables VALUES (2, 1, NULL, NULL, NULL, NULL);
+        INSERT INTO meaning_a VALUES (1, 'dog'), (2, 'cat');
+        INSERT INTO meaning_b VALUES (1, 'Hund'), (1, 'Köter'), (2, 'Katze');
+        INSERT INTO category VALUES (1, 'school');
+        INSERT INTO list_categories VALUES (1, 1);",
+    )
+    .await
+    .unwrap();
+    if !extra.is_empty() {
+        conn.execute(extra).await.unwrap();
+    }
+    conn.close().await.unwrap();
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    data
+}
+
+#[actix_rt::test]
+async fn test_read_backup() {
+    let data = gen_backup().await;
+    let user = UserId(Uuid::new_v4());
+    let backup = read_backup(data.clone(), &user).await.unwrap();
+
+    assert_eq!(2, backup.lists.len());
+    assert_eq!(1, backup.categories.len());
+    let animals = backup.lists.iter().find(|l| l.name == "animals").unwrap();
+    assert_eq!(2, animals.entries.len());
+    assert_eq!(vec![backup.categories[0].uuid], animals.categories);
+    let dog = animals.entries.iter().find(|e| e.tip == "pet").unwrap();
+    assert_eq!(3, dog.meanings.len());
+    assert_eq!(2, dog.meanings.iter().filter(|m| !m.is_a).count());
+    // stored uuid is used, future times are capped
+    let food = backup.lists.iter().find(|l| l.name == "food").unwrap();
+    assert_eq!(Uuid::parse_str(LIST_UUID).unwrap(), food.uuid);
+    assert!(food.changed <= Utc::now().naive_utc());
+    assert!(food.entries.is_empty());
+
+    // derived uuids are stable per user
+    let again = read_backup(data.clone(), &user).await.unwrap();
+    let animals_again = again.lists.iter().find(|l| l.name == "animals").unwrap();
+    assert_eq!(animals.uuid, animals_again.uuid);
+    let other = read_backup(data, &UserId(Uuid::new_v4())).await.unwrap();
+    let animals_other = other.lists.iter().find(|l| l.name == "animals").unwrap();
+    assert_ne!(animals.uuid, animals_other.uuid);
+
+    match read_backup(b"not a database".to_vec(), &user).await {
+        Err(ListError::ValidationError("backup")) => (),
+        Err(e) => panic!("invalid result: {:?}", e),
+        Ok(_) => panic!("invalid backup accepted"),
+    }
+}
+
+#[actix_rt::test]
+async fn test_read_backup_rejected() {
+    let user = UserId(Uuid::new_v4());
+    let cases = [
+        (
+            "view",
+            "DROP TABLE meaning_a; CREATE VIEW meaning_a AS SELECT voc, meaning FROM meaning_b;",
+            "backup",
+        ),
+        (
+            "trigger",
+            "CREATE TRIGGER t AFTER INSERT ON list BEGIN DELETE FROM vocables; END;",
+            "backup",
+        ),
+        ("missing table", "DROP TABLE vocables;", "backup"),
+        (
+            "too many rows",
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n LIMIT 100001)
+            INSERT INTO meaning_a SELECT 1, 'x' FROM n;",
+            "backup rows",
+        ),
+    ];
+    for (name, extra, field) in cases.iter() {
+        let data = gen_backup_with(extra).await;
+        match read_backup(data, &user).await {
+            Err(ListError::ValidationError(v)) => assert_eq!(v, *field, "{}", name),
+            Err(e) => panic!("{}: unexpected error {:?}", name, e),
+            Ok(_) => panic!("{}: accepted", name),
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn test_import_backup() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let data = gen_backup().await;
+
+    let report = dao::import_backup(conn, &user, read_backup(data.clone(), &user).await.unwrap())
+        .await
+        .unwrap();
+    assert_eq!(2, report.lists.len());
+    assert_eq!(2, report.entries);
+    assert_eq!(1, report.categories);
+    assert!(report.errors.is_empty());
+    assert_eq!(2, dao::all_lists(conn, &user).await.unwrap().len());
+
+    // importing again skips everything
+    let report = dao::import_backup(conn, &user, read_backup(data.clone(), &user).await.unwrap())
+        .await
+        .unwrap();
+    assert!(report.lists.is_empty());
+    assert_eq!(2, report.skipped_lists.len());
+    assert_eq!(0, report.entries);
+    assert_eq!(0, report.categories);
+    assert_eq!(2, dao::all_lists(conn, &user).await.unwrap().len());
+
+    // deleted categories are not restored
+    let deleted_user = register_test_user(conn, &mut rng).await;
+    let backup = read_backup(data, &deleted_user).await.unwrap();
+    sqlx::query("INSERT INTO deleted_category (user,category,created) VALUES (?,?,NOW())")
+        .bind(deleted_user.0)
+        .bind(backup.categories[0].uuid)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    let report = dao::import_backup(conn, &deleted_user, backup)
+        .await
+        .unwrap();
+    // the list with stored UUID belongs to the first user
+    assert_eq!(1, report.lists.len());
+    assert_eq!(0, report.categories);
+
+    db.drop_async().await;
+}
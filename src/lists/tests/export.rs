@@ -0,0 +1,93 @@
+use actix_web::web::Bytes;
+
+use crate::lists::export::export_list;
+use crate::lists::import;
+
+use super::*;
+
+async fn export(db: &DatabaseGuard, user: &UserId, list: &ListId, format: ExportFormat) -> String {
+    let stream = export_list(db.conn().await, user, ListId(list.0), format)
+        .await
+        .unwrap();
+    let chunks: Vec<Bytes> = stream.try_collect().await.unwrap();
+    String::from_utf8(chunks.concat()).unwrap()
+}
+
+#[actix_rt::test]
+async fn test_export_list() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let other = register_test_user(conn, &mut rng).await;
+    let list = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let quoted = EntryCreate {
+        tip: "say \"hi\"\tloud".to_owned(),
+        meanings: vec![
+            EntryMeaning {
+                value: "hello, hi".to_owned(),
+                is_a: true,
+            },
+            EntryMeaning {
+                value: "hallo".to_owned(),
+                is_a: false,
+            },
+            EntryMeaning {
+                value: "servus".to_owned(),
+                is_a: false,
+            },
+        ],
+    };
+    dao::create_entry(conn, user.clone(), list.clone(), quoted.clone(), false)
+        .await
+        .unwrap();
+    let mut plain = gen_entry(&mut rng);
+    plain.meanings[0].is_a = true;
+    plain.meanings[1].is_a = false;
+    dao::create_entry(conn, user.clone(), list.clone(), plain.clone(), false)
+        .await
+        .unwrap();
+
+    // read permission required
+    match export_list(db.conn().await, &other, list.clone(), ExportFormat::Csv).await {
+        Err(ListError::ListPermission) => (),
+        Err(e) => panic!("invalid result: {:?}", e),
+        Ok(_) => panic!("export without permission"),
+    }
+
+    // csv can be imported again
+    let csv = export(&db, &user, &list, ExportFormat::Csv).await;
+    assert!(csv.contains("\"hello, hi\",hallo; servus,\"say \"\"hi\"\"\tloud\""));
+    let opts = ImportOptions {
+        format: ImportFormat::Csv,
+        col_a: 0,
+        col_b: 1,
+        col_tip: Some(2),
+        separator: ";".to_owned(),
+        header: true,
+        dry_run: true,
+    };
+    let (entries, errors) = import::parse(&csv, &opts).unwrap();
+    assert!(errors.is_empty());
+    assert_eq!(2, entries.len());
+    let reimported = entries.iter().find(|e| e.tip == quoted.tip).unwrap();
+    assert_eq!(quoted.meanings, reimported.meanings);
+
+    let tsv = export(&db, &user, &list, ExportFormat::Tsv).await;
+    assert!(tsv.starts_with("a\tb\ttip\r\n"));
+    assert!(tsv.contains("hello, hi\thallo; servus\t\"say \"\"hi\"\"\tloud\""));
+
+    let json = export(&db, &user, &list, ExportFormat::Json).await;
+    let json: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+    assert_eq!(2, json.len());
+
+    let anki = export(&db, &user, &list, ExportFormat::AnkiTxt).await;
+    assert!(anki.starts_with("#separator:tab\n#html:false\n"));
+    assert!(anki.contains("hello, hi\thallo; servus\tsay \"hi\" loud\n"));
+    assert_eq!(4, anki.lines().count());
+
+    db.drop_async().await;
+}
@@ -0,0 +1,167 @@
+use super::*;
+
+#[actix_rt::test]
+async fn test_history_revert() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let reader = register_test_user(conn, &mut rng).await;
+    let l1 = gen_list_create(&mut rng);
+    let l1_id = dao::create_list(conn, &user, l1.clone()).await.unwrap();
+    insert_list_perm(conn, &reader.0, &l1_id.0, false, false).await;
+    let e1 = gen_entry(&mut rng);
+    let e1_id = dao::create_entry(conn, user.clone(), l1_id.clone(), e1.clone(), false)
+        .await
+        .unwrap();
+
+    // creation is recorded with the new values
+    let history = dao::history(conn, &user, &l1_id, None, 50).await.unwrap();
+    assert_eq!(2, history.len());
+    assert_eq!("create", history[0].action);
+    assert_eq!(Some(e1_id.0), history[0].entry);
+    assert_eq!(Some(e1.tip.clone()), history[0].tip);
+    assert_eq!("create", history[1].action);
+    assert_eq!(None, history[1].entry);
+    assert_eq!(Some(l1.name.clone()), history[1].name);
+
+    dao::change_list(conn, &user, l1_id.clone(), gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let e1_change = gen_entry(&mut rng);
+    dao::change_entry(conn, &user, e1_id.clone(), e1_change.clone())
+        .await
+        .unwrap();
+    dao::delete_entry(conn, &user, e1_id.clone()).await.unwrap();
+
+    // newest first, readable for all users with access
+    let history = dao::history(conn, &reader, &l1_id, None, 50).await.unwrap();
+    assert_eq!(5, history.len());
+    assert_eq!("delete", history[0].action);
+    assert_eq!(Some(e1_id.0), history[0].entry);
+    assert_eq!(Some(e1_change.tip.clone()), history[0].tip);
+    assert_eq!(e1_change.meanings.len(), history[0].meanings.len());
+    assert_eq!("change", history[1].action);
+    assert_eq!(Some(e1.tip.clone()), history[1].tip);
+    for m in e1.meanings.iter() {
+        assert!(history[1].meanings.contains(m));
+    }
+    assert_eq!("change", history[2].action);
+    assert_eq!(None, history[2].entry);
+    assert_eq!(Some(l1.name.clone()), history[2].name);
+    assert_eq!(user.0, history[2].user);
+    assert!(history[2].user_name.is_some());
+    // pagination
+    let page = dao::history(conn, &user, &l1_id, Some(history[0].id), 1)
+        .await
+        .unwrap();
+    assert_eq!(1, page.len());
+    assert_eq!(history[1].id, page[0].id);
+
+    // revert requires write permissions
+    match dao::revert_revision(conn, &reader, l1_id.clone(), history[2].id).await {
+        Err(ListError::ListPermission) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+    match dao::revert_revision(conn, &user, l1_id.clone(), history[0].id + 100).await {
+        Err(ListError::RevisionNotFound) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+
+    // list names
+    let res = dao::revert_revision(conn, &user, l1_id.clone(), history[2].id)
+        .await
+        .unwrap();
+    assert!(res.is_none());
+    let list = dao::single_list(conn, &user, &l1_id).await.unwrap();
+    test_list_change_equal(&list, &l1);
+
+    // deleted entry is re-created with a new id
+    let restored = dao::revert_revision(conn, &user, l1_id.clone(), history[0].id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_ne!(e1_id.0, restored.0);
+    let entries = all_entries(conn, &user, l1_id.clone()).await.unwrap();
+    test_entrychange_equal(entries.get(&restored.0).unwrap(), &e1_change, "re-created");
+
+    // existing entry is reverted in place
+    dao::change_entry(conn, &user, restored.clone(), gen_entry(&mut rng))
+        .await
+        .unwrap();
+    let history = dao::history(conn, &user, &l1_id, None, 50).await.unwrap();
+    assert_eq!(Some(restored.0), history[0].entry);
+    let res = dao::revert_revision(conn, &user, l1_id.clone(), history[0].id)
+        .await
+        .unwrap();
+    assert_eq!(Some(restored.0), res.map(|v| v.0));
+    let entries = all_entries(conn, &user, l1_id.clone()).await.unwrap();
+    test_entrychange_equal(entries.get(&restored.0).unwrap(), &e1_change, "reverted");
+    let history = dao::history(conn, &user, &l1_id, None, 50).await.unwrap();
+    assert_eq!("revert", history[0].action);
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_history_copy_move() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let source_id = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let e1 = gen_entry(&mut rng);
+    let e1_id = dao::create_entry(conn, user.clone(), source_id.clone(), e1.clone(), false)
+        .await
+        .unwrap();
+
+    // copies record the new list and every new entry
+    let copy_id = dao::copy_list(
+        conn,
+        &user,
+        &source_id,
+        ListCopy {
+            name: None,
+            track_source: false,
+        },
+    )
+    .await
+    .unwrap();
+    let history = dao::history(conn, &user, &copy_id, None, 50).await.unwrap();
+    assert_eq!(2, history.len());
+    assert_eq!("create", history[0].action);
+    assert_ne!(Some(e1_id.0), history[0].entry);
+    assert_eq!(Some(e1.tip.clone()), history[0].tip);
+    assert_eq!("create", history[1].action);
+    assert_eq!(None, history[1].entry);
+
+    // moved entries are recorded in the target list
+    let target_id = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    dao::merge_lists(
+        conn,
+        &user,
+        &target_id,
+        ListMerge {
+            source: source_id.0,
+            copy: false,
+        },
+    )
+    .await
+    .unwrap();
+    let history = dao::history(conn, &user, &target_id, None, 50)
+        .await
+        .unwrap();
+    assert_eq!(2, history.len());
+    assert_eq!("move", history[0].action);
+    assert_eq!(Some(e1_id.0), history[0].entry);
+    assert_eq!(Some(e1.tip.clone()), history[0].tip);
+    assert_eq!(e1.meanings.len(), history[0].meanings.len());
+
+    db.drop_async().await;
+}
@@ -0,0 +1,122 @@
+use super::*;
+use crate::lists::import::*;
+
+fn options(format: ImportFormat) -> ImportOptions {
+    ImportOptions {
+        format,
+        col_a: 0,
+        col_b: 1,
+        col_tip: Some(2),
+        separator: ";".to_owned(),
+        header: true,
+        dry_run: false,
+    }
+}
+
+#[test]
+fn test_parse_csv() {
+    let data =
+        "\u{feff}a,b,tip\r\nhouse,Haus;Gebäude,noun\r\n\"say \"\"hi\"\"\",\"grüßen, hallo\",\n\n";
+    let (entries, errors) = parse(data, &options(ImportFormat::Csv)).unwrap();
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(2, entries.len());
+    assert_eq!("noun", entries[0].tip);
+    assert_eq!(
+        vec![
+            EntryMeaning {
+                value: "house".to_owned(),
+                is_a: true
+            },
+            EntryMeaning {
+                value: "Haus".to_owned(),
+                is_a: false
+            },
+            EntryMeaning {
+                value: "Gebäude".to_owned(),
+                is_a: false
+            },
+        ],
+        entries[0].meanings
+    );
+    assert_eq!("say \"hi\"", entries[1].meanings[0].value);
+    assert_eq!("grüßen, hallo", entries[1].meanings[1].value);
+    assert_eq!("", entries[1].tip);
+}
+
+#[test]
+fn test_parse_tsv_errors() {
+    let long = "x".repeat(MAX_MEANING_LENGTH + 1);
+    let long_tip = "ü".repeat(MAX_TIP_LENGTH + 1);
+    let max = "ü".repeat(MAX_MEANING_LENGTH);
+    let data = format!(
+        "a\tb\ttip\nok\tfine\t\n{}\tb\t\n\tb\t\na\n{}\tb\t{}\n{}\tb\t\n",
+        long, "a", long_tip, max
+    );
+    let (entries, errors) = parse(&data, &options(ImportFormat::Tsv)).unwrap();
+    assert_eq!(2, entries.len());
+    let rows: Vec<usize> = errors.iter().map(|v| v.row).collect();
+    assert_eq!(vec![3, 4, 5, 6], rows);
+}
+
+#[test]
+fn test_parse_limit() {
+    let data = "a,b\n".repeat(MAX_IMPORT_ROWS + 1);
+    let mut opts = options(ImportFormat::Csv);
+    opts.col_tip = None;
+    opts.header = false;
+    match parse(&data, &opts) {
+        Err(ListError::ValidationError("rows")) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+    opts.header = true;
+    let (entries, errors) = parse(&data, &opts).unwrap();
+    assert_eq!(MAX_IMPORT_ROWS, entries.len());
+    assert!(errors.is_empty());
+}
+
+#[actix_rt::test]
+async fn test_import_entries() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let reader = register_test_user(conn, &mut rng).await;
+    let list = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    insert_list_perm(conn, &reader.0, &list.0, false, false).await;
+    let entries = vec![gen_entry(&mut rng), gen_entry(&mut rng)];
+
+    // dry run and read-only users don't insert
+    match dao::import_entries(conn, &reader, &list, entries.clone(), true).await {
+        Err(ListError::ListPermission) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+    assert_eq!(
+        0,
+        dao::import_entries(conn, &user, &list, entries.clone(), true)
+            .await
+            .unwrap()
+    );
+    assert!(all_entries(conn, &user, list.clone())
+        .await
+        .unwrap()
+        .is_empty());
+
+    assert_eq!(
+        2,
+        dao::import_entries(conn, &user, &list, entries.clone(), false)
+            .await
+            .unwrap()
+    );
+    let stored = all_entries(conn, &user, list.clone()).await.unwrap();
+    assert_eq!(2, stored.len());
+    for e in entries.iter() {
+        assert!(stored
+            .values()
+            .any(|v| v.tip == e.tip && v.meanings == e.meanings));
+    }
+
+    db.drop_async().await;
+}
@@ -146,6 +146,338 @@ async fn test_list_change() {
     db.drop_async().await;
 }
 
+#[actix_rt::test]
+async fn test_list_trash() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let shared_user = register_test_user(conn, &mut rng).await;
+    let l1_id = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let e1_id = dao::create_entry(
+        conn,
+        user.clone(),
+        l1_id.clone(),
+        gen_entry(&mut rng),
+        false,
+    )
+    .await
+    .unwrap();
+    insert_list_perm(conn, &shared_user.0, &l1_id.0, true, false).await;
+    let removed_user = register_test_user(conn, &mut rng).await;
+    insert_list_perm(conn, &removed_user.0, &l1_id.0, false, false).await;
+
+    // delete, only visible in the trash of the owner
+    dao::delete_list(conn, &user, l1_id.clone()).await.unwrap();
+    assert_eq!(0, dao::all_lists(conn, &user).await.unwrap().len());
+    assert_eq!(0, dao::all_lists(conn, &shared_user).await.unwrap().len());
+    match all_entries(conn, &user, l1_id.clone()).await {
+        Err(ListError::ListNotFound) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+    let trash = dao::trash(conn, &user, 30).await.unwrap();
+    assert_eq!(1, trash.len());
+    assert_eq!(l1_id.0, trash[0].uuid);
+    assert_eq!(30, (trash[0].purge_at - trash[0].deleted).num_days());
+    assert_eq!(0, dao::trash(conn, &shared_user, 30).await.unwrap().len());
+    assert_eq!(get_deleted_lists(conn, &user).await, vec![l1_id.0]);
+    let shared_tombstones: Vec<Uuid> =
+        sqlx::query_scalar("SELECT list FROM deleted_list_shared WHERE user = ?")
+            .bind(shared_user.0)
+            .fetch_all(&mut *conn)
+            .await
+            .unwrap();
+    assert_eq!(shared_tombstones, vec![l1_id.0]);
+    // permission removed while in the trash
+    sqlx::query("DELETE FROM list_permissions WHERE list = ? AND user = ?")
+        .bind(l1_id.0)
+        .bind(removed_user.0)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+
+    // restore, owner only
+    match dao::restore_list(conn, &shared_user, l1_id.clone()).await {
+        Err(ListError::ListPermission) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+    let t_restore = Utc::now().naive_utc();
+    dao::restore_list(conn, &user, l1_id.clone()).await.unwrap();
+    assert!(dao::all_lists(conn, &user)
+        .await
+        .unwrap()
+        .contains_key(&l1_id.0));
+    assert!(dao::all_lists(conn, &shared_user)
+        .await
+        .unwrap()
+        .contains_key(&l1_id.0));
+    assert_eq!(
+        1,
+        all_entries(conn, &user, l1_id.clone()).await.unwrap().len()
+    );
+    assert!(get_deleted_lists(conn, &user).await.is_empty());
+    // only users still having access lose their tombstone
+    let shared_tombstones: Vec<Uuid> =
+        sqlx::query_scalar("SELECT user FROM deleted_list_shared WHERE list = ?")
+            .bind(l1_id.0)
+            .fetch_all(&mut *conn)
+            .await
+            .unwrap();
+    assert_eq!(shared_tombstones, vec![removed_user.0]);
+    // entries are transferred again by sync
+    let updated = entry_updated_date(conn, &e1_id).await;
+    assert!((updated - t_restore).num_seconds().abs() < 2);
+    match dao::restore_list(conn, &user, l1_id.clone()).await {
+        Err(ListError::ListNotFound) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+
+    // purge expired trash, tombstone is kept
+    dao::delete_list(conn, &user, l1_id.clone()).await.unwrap();
+    sqlx::query("UPDATE lists SET deleted = ? WHERE uuid = ?")
+        .bind(t_restore - chrono::Duration::days(31))
+        .bind(l1_id.0)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    assert_eq!(1, dao::purge_trash(conn, 30).await.unwrap());
+    assert!(dao::trash(conn, &user, 30).await.unwrap().is_empty());
+    assert_eq!(get_deleted_lists(conn, &user).await, vec![l1_id.0]);
+    match dao::restore_list(conn, &user, l1_id.clone()).await {
+        Err(ListError::ListNotFound) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_copy_list() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let owner = register_test_user(conn, &mut rng).await;
+    let reader = register_test_user(conn, &mut rng).await;
+    let other = register_test_user(conn, &mut rng).await;
+    let l1 = gen_list_create(&mut rng);
+    let l1_id = dao::create_list(conn, &owner, l1.clone()).await.unwrap();
+    insert_list_perm(conn, &reader.0, &l1_id.0, false, false).await;
+    for _ in 0..3 {
+        dao::create_entry(
+            conn,
+            owner.clone(),
+            l1_id.clone(),
+            gen_entry(&mut rng),
+            false,
+        )
+        .await
+        .unwrap();
+    }
+
+    match dao::copy_list(
+        conn,
+        &other,
+        &l1_id,
+        ListCopy {
+            name: None,
+            track_source: false,
+        },
+    )
+    .await
+    {
+        Err(ListError::ListPermission) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+
+    // read-only users can copy and change their copy
+    let copy_id = dao::copy_list(
+        conn,
+        &reader,
+        &l1_id,
+        ListCopy {
+            name: None,
+            track_source: true,
+        },
+    )
+    .await
+    .unwrap();
+    assert_ne!(copy_id, l1_id);
+    let copy = dao::single_list(conn, &reader, &copy_id).await.unwrap();
+    test_list_change_equal(&copy, &l1);
+    assert!(!copy.foreign);
+    dao::change_list(conn, &reader, copy_id.clone(), gen_list(&mut rng))
+        .await
+        .unwrap();
+
+    let original = all_entries(conn, &owner, l1_id.clone()).await.unwrap();
+    let copied = all_entries(conn, &reader, copy_id.clone()).await.unwrap();
+    assert_eq!(original.len(), copied.len());
+    let sql_source = "SELECT source FROM entries WHERE uuid = ?";
+    for entry in copied.values() {
+        assert!(!original.contains_key(&entry.uuid));
+        let source: Option<Uuid> = sqlx::query_scalar(sql_source)
+            .bind(entry.uuid)
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        let source = &original[&source.unwrap()];
+        assert_eq!(source.tip, entry.tip);
+        assert_eq!(source.meanings.len(), entry.meanings.len());
+        for m in source.meanings.iter() {
+            assert!(entry.meanings.contains(m));
+        }
+    }
+    let source: Option<Uuid> = sqlx::query_scalar("SELECT source FROM lists WHERE uuid = ?")
+        .bind(copy_id.0)
+        .fetch_one(&mut *conn)
+        .await
+        .unwrap();
+    assert_eq!(Some(l1_id.0), source);
+
+    // renamed copy without source
+    let name = random_string(&mut rng, 7);
+    let copy_id = dao::copy_list(
+        conn,
+        &owner,
+        &l1_id,
+        ListCopy {
+            name: Some(name.clone()),
+            track_source: false,
+        },
+    )
+    .await
+    .unwrap();
+    let copy = dao::single_list(conn, &owner, &copy_id).await.unwrap();
+    assert_eq!(name, copy.name);
+    let source: Option<Uuid> = sqlx::query_scalar("SELECT source FROM lists WHERE uuid = ?")
+        .bind(copy_id.0)
+        .fetch_one(&mut *conn)
+        .await
+        .unwrap();
+    assert_eq!(None, source);
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_entry_page() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let l1_id = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let mut ids = Vec::new();
+    for value in ["delta", "Alpha", "echo", "charlie", "bravo"].iter() {
+        let entry = EntryCreate {
+            tip: String::new(),
+            meanings: vec![EntryMeaning {
+                value: value.to_string(),
+                is_a: true,
+            }],
+        };
+        let id = dao::create_entry(conn, user.clone(), l1_id.clone(), entry, false)
+            .await
+            .unwrap();
+        ids.push(id.0);
+    }
+    let query = |sort, desc, after| EntriesQuery {
+        sort,
+        desc,
+        after,
+        changed_from: None,
+        changed_to: None,
+        limit: 2,
+    };
+
+    // walk all pages, alphabetical by side A
+    let mut values = Vec::new();
+    let mut after = None;
+    loop {
+        let page = dao::entry_page(conn, &user, &l1_id, query(EntrySort::A, false, after))
+            .await
+            .unwrap();
+        assert!(page.entries.len() <= 2);
+        values.extend(
+            page.entries
+                .into_iter()
+                .map(|e| e.meanings[0].value.clone()),
+        );
+        after = page.next;
+        if after.is_none() {
+            break;
+        }
+    }
+    assert_eq!(vec!["Alpha", "bravo", "charlie", "delta", "echo"], values);
+
+    let page = dao::entry_page(conn, &user, &l1_id, query(EntrySort::A, true, None))
+        .await
+        .unwrap();
+    assert_eq!("echo", page.entries[0].meanings[0].value);
+    assert_eq!("delta", page.entries[1].meanings[0].value);
+
+    // sort keys follow changed meanings, synced entries can have no tip
+    let change = EntryChange {
+        tip: String::new(),
+        meanings: vec![EntryMeaning {
+            value: "aardvark".to_owned(),
+            is_a: true,
+        }],
+    };
+    dao::change_entry(conn, &user, EntryId(ids[2]), change)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE entries SET tip = NULL WHERE uuid = ?")
+        .bind(ids[2])
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    let page = dao::entry_page(conn, &user, &l1_id, query(EntrySort::A, false, None))
+        .await
+        .unwrap();
+    assert_eq!(ids[2], page.entries[0].uuid);
+    assert_eq!("", page.entries[0].tip);
+    assert_eq!("Alpha", page.entries[1].meanings[0].value);
+
+    // stable order by creation, every entry exactly once
+    let mut seen = Vec::new();
+    let mut after = None;
+    loop {
+        let page = dao::entry_page(conn, &user, &l1_id, query(EntrySort::Created, true, after))
+            .await
+            .unwrap();
+        seen.extend(page.entries.into_iter().map(|e| e.uuid));
+        after = page.next;
+        if after.is_none() {
+            break;
+        }
+    }
+    seen.sort();
+    ids.sort();
+    assert_eq!(ids, seen);
+
+    // changed range
+    let mut ranged = query(EntrySort::Changed, false, None);
+    ranged.changed_from = Some(Utc::now().naive_utc() + chrono::Duration::hours(1));
+    let page = dao::entry_page(conn, &user, &l1_id, ranged).await.unwrap();
+    assert!(page.entries.is_empty());
+    assert!(page.next.is_none());
+
+    let invalid = query(EntrySort::Changed, false, Some("invalid".to_owned()));
+    match dao::entry_page(conn, &user, &l1_id, invalid).await {
+        Err(ListError::ValidationError("after")) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+
+    db.drop_async().await;
+}
+
 // TODO: verify shared list changes and entry changes
 
 #[actix_rt::test]
@@ -163,11 +495,11 @@ async fn test_entry_change() {
         .unwrap();
 
     let e1 = gen_entry(&mut rng);
-    let e1_id = dao::create_entry(&mut conn, user.clone(), l1_id.clone(), e1.clone())
+    let e1_id = dao::create_entry(&mut conn, user.clone(), l1_id.clone(), e1.clone(), false)
         .await
         .unwrap();
 
-    let ret = dao::entries(&mut conn, &user, l1_id.clone()).await.unwrap();
+    let ret = all_entries(&mut conn, &user, l1_id.clone()).await.unwrap();
     assert_eq!(ret.len(), 1);
     test_entrychange_equal(ret.get(&e1_id.0).unwrap(), &e1, "created entry");
     // check updated date
@@ -180,7 +512,7 @@ async fn test_entry_change() {
         .await
         .unwrap();
 
-    let ret = dao::entries(&mut conn, &user, l1_id.clone()).await.unwrap();
+    let ret = all_entries(&mut conn, &user, l1_id.clone()).await.unwrap();
     assert_eq!(ret.len(), 1);
     test_entrychange_equal(ret.get(&e1_id.0).unwrap(), &change, "changed entry");
 
@@ -193,7 +525,7 @@ async fn test_entry_change() {
         .await
         .unwrap();
 
-    let ret = dao::entries(&mut conn, &user, l1_id.clone()).await.unwrap();
+    let ret = all_entries(&mut conn, &user, l1_id.clone()).await.unwrap();
     assert_eq!(0, ret.len());
     // test for deletion entry
     let deleted = get_deleted_entries(&mut conn, &l1_id).await;
@@ -206,19 +538,51 @@ async fn test_entry_change() {
     db.drop_async().await;
 }
 
-fn test_list_change_equal(list: &List, change: &ListChange) {
-    assert_eq!(list.name, change.name);
-    assert_eq!(list.name_b, change.name_b);
-    assert_eq!(list.name_a, change.name_a);
-}
-
 fn test_entry_equal(entry: &Entry, expected: &Entry) {
     assert_eq!(entry.uuid, expected.uuid);
     assert_eq!(entry.tip, expected.tip);
     assert_eq!(entry.meanings, expected.meanings);
 }
 
-fn test_entrychange_equal(entry: &Entry, expected: &EntryChange, msg: &'static str) {
-    assert_eq!(entry.tip, expected.tip, "{}", msg);
-    assert_eq!(entry.meanings, expected.meanings, "{}", msg);
+#[actix_rt::test]
+async fn test_entry_routes_json() {
+    use actix_web::{test, App};
+
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+    let state = db.state();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let bearer = auth_header(conn, &state, &user).await;
+    let list = dao::create_list(conn, &user, gen_list(&mut rng))
+        .await
+        .unwrap();
+    let app = test::init_service(App::new().app_data(state).configure(routes::init)).await;
+
+    // entries are sent as JSON body
+    let create = gen_entry(&mut rng);
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/lists/{}/entry", list.0))
+        .insert_header(bearer.clone())
+        .set_json(&create)
+        .to_request();
+    let entry: Uuid = test::read_body_json(test::call_service(&app, req).await).await;
+    let change = gen_entry(&mut rng);
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/lists/{}/entry/{}", list.0, entry))
+        .insert_header(bearer)
+        .set_json(&change)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(res.status().is_success());
+
+    let tip = sqlx::query_scalar::<_, String>("SELECT tip FROM entries WHERE uuid = ?")
+        .bind(entry)
+        .fetch_one(&mut *conn)
+        .await
+        .unwrap();
+    assert_eq!(change.tip, tip);
+
+    db.drop_async().await;
 }
@@ -0,0 +1,393 @@
+use crate::lists::dedup::*;
+
+use super::*;
+
+fn meaning(value: &str, is_a: bool) -> EntryMeaning {
+    EntryMeaning {
+        value: value.to_owned(),
+        is_a,
+    }
+}
+
+/// Stored meaning, normalized like the generated column
+fn stored(value: &str, normalized: &str, is_a: bool) -> StoredMeaning {
+    StoredMeaning {
+        value: value.to_owned(),
+        is_a,
+        normalized: normalized.to_owned(),
+    }
+}
+
+#[test]
+fn test_is_duplicate() {
+    let dog = vec![stored("dog", "dog", true), stored("Hund", "hund", false)];
+    assert!(is_duplicate(
+        &dog,
+        &[
+            stored(" Dog", "dog", true),
+            stored("Köter", "köter", false),
+            stored("hund", "hund", false)
+        ]
+    ));
+    // both sides have to match
+    assert!(!is_duplicate(
+        &dog,
+        &[stored("dog", "dog", true), stored("Köter", "köter", false)]
+    ));
+    // missing sides are ignored
+    assert!(is_duplicate(&dog, &[stored("DOG", "dog", true)]));
+    assert!(!is_duplicate(
+        &[stored("dog", "dog", true)],
+        &[stored("dog", "dog", false)]
+    ));
+    // only normalized values are compared
+    assert!(!is_duplicate(
+        &[stored("dog", "dog", true)],
+        &[stored("dog", "dogs", true)]
+    ));
+    assert!(!is_duplicate(&[], &[]));
+}
+
+#[test]
+fn test_merge_entry() {
+    let entry = |tip: &str, meanings| StoredEntry {
+        uuid: Uuid::new_v4(),
+        tip: tip.to_owned(),
+        meanings,
+    };
+    let mut target = entry(
+        "",
+        vec![stored("dog", "dog", true), stored("Hund", "hund", false)],
+    );
+    let changed = merge_entry(
+        &mut target,
+        entry(
+            "pet",
+            vec![stored("Dog", "dog", true), stored("Köter", "köter", false)],
+        ),
+    );
+    assert!(changed);
+    assert_eq!("pet", target.tip);
+    assert_eq!(3, target.meanings.len());
+    assert!(target
+        .meanings
+        .iter()
+        .any(|m| m.value == "Köter" && !m.is_a));
+
+    let changed = merge_entry(
+        &mut target,
+        entry("other", vec![stored("hund ", "hund", false)]),
+    );
+    assert!(!changed);
+    assert_eq!("pet", target.tip);
+    assert_eq!(3, target.meanings.len());
+}
+
+#[actix_rt::test]
+async fn test_normalized_whitespace() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let list = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let create = |value: &str| EntryCreate {
+        tip: String::new(),
+        meanings: vec![meaning(value, true)],
+    };
+    let hot_dog = dao::create_entry(conn, user.clone(), list.clone(), create("hot dog"), true)
+        .await
+        .unwrap();
+
+    // tabs, newlines and repeated whitespace are collapsed
+    for value in ["Hot\tDog", " hot\ndog\n", "HOT \t\r\n DOG"].iter() {
+        match dao::create_entry(conn, user.clone(), list.clone(), create(value), true).await {
+            Err(ListError::DuplicateEntry(id)) => assert_eq!(hot_dog.0, id),
+            v => panic!("invalid result for {:?}: {:?}", value, v),
+        }
+    }
+    // rejected entries aren't stored
+    assert_eq!(
+        1,
+        all_entries(conn, &user, list.clone()).await.unwrap().len()
+    );
+    let duplicates = dao::duplicates(conn, &user, &list).await.unwrap();
+    assert!(duplicates.is_empty());
+
+    let other = dao::create_entry(conn, user.clone(), list.clone(), create("hot\tdog"), false)
+        .await
+        .unwrap();
+    let duplicates = dao::duplicates(conn, &user, &list).await.unwrap();
+    assert_eq!(1, duplicates.len());
+    assert_eq!("hot dog", duplicates[0].value);
+    let mut expected = vec![hot_dog.0, other.0];
+    expected.sort();
+    let mut found = duplicates[0].entries.clone();
+    found.sort();
+    assert_eq!(expected, found);
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_merge_lists() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let shared = register_test_user(conn, &mut rng).await;
+    let a_id = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let b_id = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    insert_list_perm(conn, &shared.0, &b_id.0, true, false).await;
+
+    let a_dog = dao::create_entry(
+        conn,
+        user.clone(),
+        a_id.clone(),
+        EntryCreate {
+            tip: String::new(),
+            meanings: vec![meaning("dog", true), meaning("Hund", false)],
+        },
+        false,
+    )
+    .await
+    .unwrap();
+    let b_dog = dao::create_entry(
+        conn,
+        user.clone(),
+        b_id.clone(),
+        EntryCreate {
+            tip: "pet".to_owned(),
+            meanings: vec![
+                meaning("Dog ", true),
+                meaning("Köter", false),
+                meaning("hund", false),
+            ],
+        },
+        false,
+    )
+    .await
+    .unwrap();
+    let b_other = dao::create_entry(conn, user.clone(), b_id.clone(), gen_entry(&mut rng), false)
+        .await
+        .unwrap();
+
+    // owner of both lists only
+    let merge = |copy| ListMerge {
+        source: b_id.0,
+        copy,
+    };
+    match dao::merge_lists(conn, &shared, &a_id, merge(false)).await {
+        Err(ListError::ListPermission) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+    match dao::merge_lists(
+        conn,
+        &user,
+        &a_id,
+        ListMerge {
+            source: a_id.0,
+            copy: false,
+        },
+    )
+    .await
+    {
+        Err(ListError::ValidationError("source")) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+
+    // copying keeps the source list
+    let report = dao::merge_lists(conn, &user, &a_id, merge(true))
+        .await
+        .unwrap();
+    assert_eq!(1, report.entries);
+    assert_eq!(1, report.merged);
+    assert_eq!(
+        2,
+        all_entries(conn, &user, b_id.clone()).await.unwrap().len()
+    );
+    let entries = all_entries(conn, &user, a_id.clone()).await.unwrap();
+    assert_eq!(2, entries.len());
+    assert!(!entries.contains_key(&b_other.0));
+    let dog = &entries[&a_dog.0];
+    assert_eq!("pet", dog.tip);
+    assert_eq!(3, dog.meanings.len());
+
+    // moving, the copied entry is now a duplicate too
+    let report = dao::merge_lists(conn, &user, &a_id, merge(false))
+        .await
+        .unwrap();
+    assert_eq!(0, report.entries);
+    assert_eq!(2, report.merged);
+    let mut deleted = get_deleted_entries(conn, &b_id).await;
+    deleted.sort();
+    let mut expected = vec![b_dog.0, b_other.0];
+    expected.sort();
+    assert_eq!(expected, deleted);
+    assert_eq!(get_deleted_lists(conn, &user).await, vec![b_id.0]);
+    assert!(!dao::all_lists(conn, &shared)
+        .await
+        .unwrap()
+        .contains_key(&b_id.0));
+    assert_eq!(
+        2,
+        all_entries(conn, &user, a_id.clone()).await.unwrap().len()
+    );
+
+    // unique entries are moved with their ids
+    let c_id = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let c_entry = dao::create_entry(conn, user.clone(), c_id.clone(), gen_entry(&mut rng), false)
+        .await
+        .unwrap();
+    // moving records the merging user as author of the change
+    sqlx::query("UPDATE entries SET changed_by = NULL WHERE uuid = ?")
+        .bind(c_entry.0)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    let report = dao::merge_lists(
+        conn,
+        &user,
+        &a_id,
+        ListMerge {
+            source: c_id.0,
+            copy: false,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(1, report.entries);
+    assert_eq!(0, report.merged);
+    let entries = all_entries(conn, &user, a_id.clone()).await.unwrap();
+    assert_eq!(3, entries.len());
+    assert_eq!(Some(user.0), entries[&c_entry.0].changed_by);
+    assert!(get_deleted_entries(conn, &c_id).await.is_empty());
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_entry_duplicates() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let l1_id = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let dog = EntryCreate {
+        tip: String::new(),
+        meanings: vec![meaning("dog", true), meaning("Hund", false)],
+    };
+    let e1 = dao::create_entry(conn, user.clone(), l1_id.clone(), dog.clone(), true)
+        .await
+        .unwrap();
+    assert!(dao::duplicates(conn, &user, &l1_id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let dog_again = EntryCreate {
+        tip: String::new(),
+        meanings: vec![meaning(" DOG", true), meaning("hund ", false)],
+    };
+    match dao::create_entry(conn, user.clone(), l1_id.clone(), dog_again.clone(), true).await {
+        Err(ListError::DuplicateEntry(entry)) => assert_eq!(e1.0, entry),
+        v => panic!("invalid result: {:?}", v),
+    }
+    // same side A meaning only is not a duplicate
+    let hound = EntryCreate {
+        tip: String::new(),
+        meanings: vec![meaning("dog", true), meaning("Jagdhund", false)],
+    };
+    let e2 = dao::create_entry(conn, user.clone(), l1_id.clone(), hound, true)
+        .await
+        .unwrap();
+    let e3 = dao::create_entry(conn, user.clone(), l1_id.clone(), dog_again, false)
+        .await
+        .unwrap();
+
+    let duplicates = dao::duplicates(conn, &user, &l1_id).await.unwrap();
+    assert_eq!(2, duplicates.len());
+    let side_a = duplicates.iter().find(|d| d.is_a).unwrap();
+    assert_eq!("dog", side_a.value);
+    let mut expected = vec![e1.0, e2.0, e3.0];
+    expected.sort();
+    assert_eq!(expected, side_a.entries);
+    let side_b = duplicates.iter().find(|d| !d.is_a).unwrap();
+    assert_eq!("hund", side_b.value);
+    let mut expected = vec![e1.0, e3.0];
+    expected.sort();
+    assert_eq!(expected, side_b.entries);
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_merge_lists_null_tip() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let a_id = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let b_id = dao::create_list(conn, &user, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    let dog = || EntryCreate {
+        tip: "pet".to_owned(),
+        meanings: vec![meaning("dog", true), meaning("Hund", false)],
+    };
+    let a_dog = dao::create_entry(conn, user.clone(), a_id.clone(), dog(), false)
+        .await
+        .unwrap();
+    let b_dog = dao::create_entry(conn, user.clone(), b_id.clone(), dog(), false)
+        .await
+        .unwrap();
+    let b_other = dao::create_entry(conn, user.clone(), b_id.clone(), gen_entry(&mut rng), false)
+        .await
+        .unwrap();
+    // tips of synced entries can be NULL
+    sqlx::query("UPDATE entries SET tip = NULL WHERE uuid IN (?,?,?)")
+        .bind(a_dog.0)
+        .bind(b_dog.0)
+        .bind(b_other.0)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+
+    let report = dao::merge_lists(
+        conn,
+        &user,
+        &a_id,
+        ListMerge {
+            source: b_id.0,
+            copy: false,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(1, report.entries);
+    assert_eq!(1, report.merged);
+    let moved: Vec<Uuid> = sqlx::query_scalar("SELECT uuid FROM entries WHERE list = ?")
+        .bind(a_id.0)
+        .fetch_all(&mut *conn)
+        .await
+        .unwrap();
+    assert_eq!(2, moved.len());
+    assert!(moved.contains(&b_other.0));
+
+    db.drop_async().await;
+}
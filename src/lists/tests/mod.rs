@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use chrono::Utc;
 use futures::TryStreamExt;
 use rand::prelude::ThreadRng;
@@ -8,7 +10,13 @@ use super::models::*;
 use super::*;
 use crate::prelude::tests::*;
 
+mod backup;
+mod export;
+mod history;
+mod import;
 mod list_basics;
+mod merge;
+mod search;
 mod sharing;
 
 fn gen_list(rng: &mut ThreadRng) -> ListChange {
@@ -44,6 +52,17 @@ fn gen_meanings(rng: &mut ThreadRng, amount: usize) -> Vec<EntryMeaning> {
         .collect()
 }
 
+fn test_list_change_equal(list: &List, change: &ListChange) {
+    assert_eq!(list.name, change.name);
+    assert_eq!(list.name_b, change.name_b);
+    assert_eq!(list.name_a, change.name_a);
+}
+
+fn test_entrychange_equal(entry: &Entry, expected: &EntryChange, msg: &'static str) {
+    assert_eq!(entry.tip, expected.tip, "{}", msg);
+    assert_eq!(entry.meanings, expected.meanings, "{}", msg);
+}
+
 /// Insert list permissions, test only
 async fn insert_list_perm(sql: &mut DbConn, user: &Uuid, list: &Uuid, change: bool, reshare: bool) {
     let t_now = Utc::now().naive_utc();
@@ -91,3 +110,29 @@ async fn entry_updated_date(sql: &mut MySqlConnection, entry: &EntryId) -> Times
         .await
         .unwrap()
 }
+
+/// All entries of the list keyed by uuid, read through all pages
+async fn all_entries(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    list: ListId,
+) -> Result<HashMap<Uuid, Entry>> {
+    let mut entries = HashMap::new();
+    let mut after = None;
+    loop {
+        let query = EntriesQuery {
+            sort: EntrySort::default(),
+            desc: false,
+            after,
+            changed_from: None,
+            changed_to: None,
+            limit: 500,
+        };
+        let page = dao::entry_page(&mut *sql, user, &list, query).await?;
+        entries.extend(page.entries.into_iter().map(|e| (e.uuid, e)));
+        match page.next {
+            Some(next) => after = Some(next),
+            None => return Ok(entries),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::lists::search::*;
+
+use super::*;
+
+#[test]
+fn test_boolean_query() {
+    assert_eq!(Some("+dog*".to_owned()), boolean_query("dog"));
+    assert_eq!(
+        Some("+big* +dog*".to_owned()),
+        boolean_query(" +big  -dog* ")
+    );
+    assert_eq!(Some("+straße*".to_owned()), boolean_query("\"straße\""));
+    assert_eq!(None, boolean_query(" *+-\"() "));
+}
+
+fn query(q: &str, is_a: Option<bool>) -> SearchQuery {
+    SearchQuery {
+        q: q.to_owned(),
+        is_a,
+        offset: 0,
+        limit: 20,
+    }
+}
+
+#[actix_rt::test]
+async fn test_search() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let owner = register_test_user(conn, &mut rng).await;
+    let other = register_test_user(conn, &mut rng).await;
+    let word = random_string(&mut rng, 9);
+
+    let mut own = gen_list_create(&mut rng);
+    own.name = format!("{} list", word);
+    let own_id = dao::create_list(conn, &user, own.clone()).await.unwrap();
+    let shared_id = dao::create_list(conn, &owner, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    insert_list_perm(conn, &user.0, &shared_id.0, false, false).await;
+    let hidden_id = dao::create_list(conn, &other, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+
+    let entry = |value: &str, is_a: bool| EntryCreate {
+        tip: String::new(),
+        meanings: vec![EntryMeaning {
+            value: value.to_owned(),
+            is_a,
+        }],
+    };
+    let shared_entry = dao::create_entry(
+        conn,
+        owner.clone(),
+        shared_id.clone(),
+        entry(&format!("{}suffix", word), false),
+        false,
+    )
+    .await
+    .unwrap();
+    dao::create_entry(
+        conn,
+        other.clone(),
+        hidden_id.clone(),
+        entry(&word, false),
+        false,
+    )
+    .await
+    .unwrap();
+
+    // prefix match over own and shared lists
+    let prefix: String = word.chars().take(6).collect();
+    let hits = dao::search(conn, &user, query(&prefix, None))
+        .await
+        .unwrap();
+    assert_eq!(2, hits.len());
+    let list_hit = hits.iter().find(|h| h.entry.is_none()).unwrap();
+    assert_eq!(own_id.0, list_hit.list);
+    assert_eq!(own.name, list_hit.list_name);
+    assert!(!list_hit.foreign);
+    let entry_hit = hits.iter().find(|h| h.entry.is_some()).unwrap();
+    assert_eq!(Some(shared_entry.0), entry_hit.entry);
+    assert_eq!(shared_id.0, entry_hit.list);
+    assert!(entry_hit.foreign);
+    assert_eq!(1, entry_hit.meanings.len());
+
+    // side filter excludes list names
+    let hits = dao::search(conn, &user, query(&prefix, Some(false)))
+        .await
+        .unwrap();
+    assert_eq!(1, hits.len());
+    assert_eq!(Some(shared_entry.0), hits[0].entry);
+    assert!(dao::search(conn, &user, query(&prefix, Some(true)))
+        .await
+        .unwrap()
+        .is_empty());
+
+    // pagination
+    let mut paged = query(&prefix, None);
+    paged.offset = 1;
+    assert_eq!(1, dao::search(conn, &user, paged).await.unwrap().len());
+
+    match dao::search(conn, &user, query("()", None)).await {
+        Err(ListError::ValidationError("q")) => (),
+        v => panic!("invalid result: {:?}", v),
+    }
+
+    // every word is matched as prefix of any word, not in the middle of words
+    let mut list = gen_list_create(&mut rng);
+    list.name = random_string(&mut rng, 12);
+    let words_id = dao::create_list(conn, &other, list).await.unwrap();
+    let words_entry = dao::create_entry(
+        conn,
+        other.clone(),
+        words_id.clone(),
+        entry("running past the finish", true),
+        false,
+    )
+    .await
+    .unwrap();
+    for (q, found) in [
+        ("fini", true),
+        ("Finish Runn", true),
+        ("inish", false),
+        ("finish walking", false),
+    ]
+    .iter()
+    {
+        let hits = dao::search(conn, &other, query(q, Some(true)))
+            .await
+            .unwrap();
+        assert_eq!(
+            *found,
+            hits.iter().any(|h| h.entry == Some(words_entry.0)),
+            "wrong result for {}",
+            q
+        );
+    }
+
+    db.drop_async().await;
+}
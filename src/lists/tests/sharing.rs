@@ -110,3 +110,194 @@ async fn test_sharecode_invalid_data() {
 
     db.drop_async().await;
 }
+
+#[actix_rt::test]
+async fn test_set_share_permissions() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let user2 = register_test_user(conn, &mut rng).await;
+    let list_id = dao::create_list(conn, &user, gen_list(&mut rng))
+        .await
+        .unwrap();
+    insert_list_perm(conn, &user2.0, &list_id.0, false, false).await;
+
+    let perms = UserPermissions {
+        write: true,
+        reshare: false,
+    };
+    dao::set_share_permissions(conn, &user, &list_id, &user2, perms)
+        .await
+        .unwrap();
+    let stored = sqlx::query_as::<_, (bool, bool)>(
+        "SELECT `write`,reshare FROM list_permissions WHERE list = ? AND user = ?",
+    )
+    .bind(list_id.0)
+    .bind(user2.0)
+    .fetch_one(&mut *conn)
+    .await
+    .unwrap();
+    assert_eq!((true, false), stored);
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_has_list_perm() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let reader = register_test_user(conn, &mut rng).await;
+    let writer = register_test_user(conn, &mut rng).await;
+    let list_id = dao::create_list(conn, &user, gen_list(&mut rng))
+        .await
+        .unwrap();
+    insert_list_perm(conn, &reader.0, &list_id.0, false, false).await;
+    insert_list_perm(conn, &writer.0, &list_id.0, true, false).await;
+
+    assert!(
+        dao::has_list_perm(conn, &reader, &list_id, dao::Permission::READ)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !dao::has_list_perm(conn, &reader, &list_id, dao::Permission::WRITE)
+            .await
+            .unwrap()
+    );
+    assert!(
+        dao::has_list_perm(conn, &writer, &list_id, dao::Permission::WRITE)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !dao::has_list_perm(conn, &writer, &list_id, dao::Permission::RESHARE)
+            .await
+            .unwrap()
+    );
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_sharing_renamed_user() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let user2 = register_test_user(conn, &mut rng).await;
+    let list_id = dao::create_list(conn, &user, gen_list(&mut rng))
+        .await
+        .unwrap();
+    insert_list_perm(conn, &user2.0, &list_id.0, false, false).await;
+
+    let name = random_string(&mut rng, 12);
+    crate::users::dao::update_user(conn, &user2, Some(&name), None)
+        .await
+        .unwrap();
+    let shared = dao::list_sharing(conn, &user, &list_id).await.unwrap();
+    assert_eq!(name, shared[&user2.0].name);
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_sharing_audit_log() {
+    use crate::audit;
+
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let user2 = register_test_user(conn, &mut rng).await;
+    let list_id = dao::create_list(conn, &user, gen_list(&mut rng))
+        .await
+        .unwrap();
+    let share_data = NewTokenData {
+        write: false,
+        reshare: false,
+        reusable: false,
+        deadline: random_future_date(&mut rng),
+    };
+    let res = dao::generate_share_code(conn, &user, &list_id, share_data)
+        .await
+        .unwrap();
+    dao::use_share_code(conn, &user2, &res.token_a, &res.token_b)
+        .await
+        .unwrap();
+    let perms = UserPermissions {
+        write: true,
+        reshare: false,
+    };
+    dao::set_share_permissions(conn, &user, &list_id, &user2, perms)
+        .await
+        .unwrap();
+    let shared = dao::list_sharing(conn, &user, &list_id).await.unwrap();
+    assert!(shared[&user2.0].write);
+
+    let events = |v: Vec<audit::Activity>| v.into_iter().map(|v| v.event).collect::<Vec<_>>();
+    let owner_log = audit::activity(conn, &user, None, 10).await.unwrap();
+    assert_eq!(
+        vec![
+            "share_permissions_changed",
+            "sharecode_used",
+            "sharecode_created"
+        ],
+        events(owner_log)
+    );
+    // redeemed by and permissions of user2
+    let user2_log = audit::activity(conn, &user2, None, 10).await.unwrap();
+    assert_eq!(
+        vec!["share_permissions_changed", "sharecode_used"],
+        events(user2_log)
+    );
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_shared_entry_authors() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let owner = register_test_user(conn, &mut rng).await;
+    let writer = register_test_user(conn, &mut rng).await;
+    let l1_id = dao::create_list(conn, &owner, gen_list_create(&mut rng))
+        .await
+        .unwrap();
+    insert_list_perm(conn, &writer.0, &l1_id.0, true, false).await;
+
+    let start = Utc::now().naive_utc() - chrono::Duration::seconds(1);
+    let e1 = dao::create_entry(
+        conn,
+        owner.clone(),
+        l1_id.clone(),
+        gen_entry(&mut rng),
+        false,
+    )
+    .await
+    .unwrap();
+    let entries = all_entries(conn, &writer, l1_id.clone()).await.unwrap();
+    let entry = &entries[&e1.0];
+    assert!(entry.created >= start);
+    assert_eq!(Some(owner.0), entry.created_by);
+    assert_eq!(Some(owner.0), entry.changed_by);
+
+    dao::change_entry(conn, &writer, e1.clone(), gen_entry(&mut rng))
+        .await
+        .unwrap();
+    let entries = all_entries(conn, &owner, l1_id.clone()).await.unwrap();
+    let changed = &entries[&e1.0];
+    assert_eq!(entry.created, changed.created);
+    assert_eq!(Some(owner.0), changed.created_by);
+    assert_eq!(Some(writer.0), changed.changed_by);
+
+    db.drop_async().await;
+}
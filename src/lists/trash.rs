@@ -0,0 +1,28 @@
+//! Background job purging lists from the trash after their retention
+use std::time::Duration;
+
+use sqlx::MySqlPool;
+
+use super::*;
+
+/// Interval of the trash cleanup
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Run [dao::purge_trash] periodically on the current runtime
+pub fn spawn_purge(pool: MySqlPool, retention: u32) {
+    actix_rt::spawn(async move {
+        let mut timer = actix_rt::time::interval(PURGE_INTERVAL);
+        loop {
+            timer.tick().await;
+            let res = match pool.acquire().await {
+                Ok(mut conn) => dao::purge_trash(&mut conn, retention).await,
+                Err(e) => Err(e.into()),
+            };
+            match res {
+                Ok(0) => (),
+                Ok(purged) => info!(purged, "purged lists from trash"),
+                Err(e) => warn!(?e, "purging trash failed"),
+            }
+        }
+    });
+}
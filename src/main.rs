@@ -1,7 +1,11 @@
 use std::str::FromStr;
+use std::time::Duration;
 
 use actix_identity::{CookieIdentityPolicy, IdentityService};
-use actix_web::{cookie::SameSite, web, App, HttpServer};
+use actix_web::{
+    cookie::{Key, SameSite},
+    web, App, HttpServer,
+};
 use color_eyre::eyre::Result;
 use sqlx::{
     mysql::{MySqlConnectOptions, MySqlPoolOptions},
@@ -12,6 +16,7 @@ use tracing_actix_web::TracingLogger;
 use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
 use uuid::Uuid;
 
+mod audit;
 mod config;
 mod lists;
 mod prelude;
@@ -24,10 +29,11 @@ pub type Pool = MySqlPool;
 
 const SERVER_ID: &str = "server_id";
 const SESSION_KEY: &str = "session_key";
+const TOKEN_KEY: &str = "token_key";
 #[cfg(debug_assertions)]
-const SECURE_COOKIE: bool = false;
+pub const SECURE_COOKIE: bool = false;
 #[cfg(not(debug_assertions))]
-const SECURE_COOKIE: bool = true;
+pub const SECURE_COOKIE: bool = true;
 
 fn init_telemetry() {
     let app_name = env!("CARGO_BIN_NAME");
@@ -98,8 +104,8 @@ async fn main_() -> Result<()> {
         .port(config.database.port)
         .username(&config.database.user)
         .database(&config.database.db);
-    if let Some(pw) = config.database.password {
-        options = options.password(&pw);
+    if let Some(pw) = &config.database.password {
+        options = options.password(pw);
     }
 
     let db_pool = MySqlPoolOptions::new()
@@ -156,9 +162,41 @@ async fn main_() -> Result<()> {
         }
     };
 
+    let token_key = match server::load_setting(&db_pool, TOKEN_KEY).await? {
+        Some(v) => base64::decode(&v)?,
+        None => {
+            let random_bytes: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+            server::set_setting(&db_pool, TOKEN_KEY, &base64::encode(&random_bytes), false).await?;
+            random_bytes
+        }
+    };
+
+    let listen_ip = config.listen_ip.clone();
+    let listen_port = config.listen_port;
+    let login_limiter = users::ratelimit::LoginLimiter::new(config.login_limit.clone());
+    let oidc = users::oidc::OidcClient::new(config.oidc.clone());
+    let mailer: Box<dyn users::mail::MailSender> = match config.mail.as_ref() {
+        Some(mail) => Box::new(users::mail::SmtpMailSender::new(mail)?),
+        None => {
+            info!("No mail server configured, email changes are unavailable");
+            Box::new(users::mail::NoMailSender)
+        }
+    };
+    audit::spawn_purge(db_pool.clone(), config.audit.retention);
+    lists::trash::spawn_purge(db_pool.clone(), config.trash.retention);
+    users::deletion::spawn_finalizer(
+        db_pool.clone(),
+        Duration::from_secs(config.account_deletion.finalize_interval),
+    );
     let state = web::Data::new(state::State {
+        config,
         sql: db_pool,
         id: server_id,
+        token_keys: users::auth::TokenKeys::new(&token_key),
+        login_limiter,
+        oidc,
+        mailer,
+        cookie_key: Key::derive_from(&session_key),
     });
 
     let server = HttpServer::new(move || {
@@ -178,11 +216,11 @@ async fn main_() -> Result<()> {
             .configure(sync::routes::init) // init sync api routes
             .configure(lists::routes::init) // init lists routes
     })
-    .bind((config.listen_ip.as_ref(), config.listen_port))?;
+    .bind((listen_ip.as_ref(), listen_port))?;
 
     info!(
         "Starting server, listening on {}:{}",
-        config.listen_ip, config.listen_port
+        listen_ip, listen_port
     );
     server.run().await?;
     info!("Shutting down");
@@ -6,6 +6,7 @@ pub type Timestamp = NaiveDateTime;
 pub type DbConn = sqlx::MySqlConnection;
 pub use crate::state::AppState;
 pub use crate::users::update_last_seen;
+pub use crate::users::AuthUser;
 pub use color_eyre::eyre::Context;
 pub use serde::{Deserialize, Serialize};
 pub use tracing::*;
@@ -90,8 +91,18 @@ pub mod tests {
     };
     use uuid::Uuid;
 
+    use actix_web::{cookie::Key, http::header};
+
     use crate::{
-        users::user::{KeyType, RegisterClaims},
+        config::{Database, Settings},
+        state::{AppState, State},
+        users::{
+            auth::{issue_access_token, TokenKeys},
+            mail::NoMailSender,
+            oidc::OidcClient,
+            ratelimit::LoginLimiter,
+            user::{KeyType, LoginMethod, RegisterClaims},
+        },
         Pool,
     };
 
@@ -172,6 +183,42 @@ pub mod tests {
             self.db.acquire().await.unwrap()
         }
 
+        /// App state on top of this database, for calling routes
+        pub fn state(&self) -> AppState {
+            let config = Settings {
+                database: Database {
+                    host: String::from("127.0.0.1"),
+                    port: 3306,
+                    user: String::from("root"),
+                    password: None,
+                    db: self.db_name.clone(),
+                    max_conn: 1,
+                },
+                listen_ip: String::from("127.0.0.1"),
+                listen_port: 0,
+                key_algorithms: Default::default(),
+                login_limit: Default::default(),
+                password_hashing: Default::default(),
+                account_deletion: Default::default(),
+                audit: Default::default(),
+                trash: Default::default(),
+                oidc: Default::default(),
+                mail: None,
+            };
+            AppState::new(State {
+                login_limiter: LoginLimiter::new(config.login_limit.clone()),
+                oidc: OidcClient::new(config.oidc.clone()),
+                config,
+                sql: self.db.clone(),
+                id: Uuid::new_v4(),
+                token_keys: TokenKeys::new(
+                    &random_string(&mut rand::thread_rng(), 32).into_bytes(),
+                ),
+                mailer: Box::new(NoMailSender),
+                cookie_key: Key::generate(),
+            })
+        }
+
         /// Has to be called manually, hack due to problem with async in drop code
         pub async fn drop_async(self) {
             sqlx::query(format!("DROP DATABASE IF EXISTS `{}`", self.db_name).as_str())
@@ -242,9 +289,22 @@ pub mod tests {
     /// Generate user and register
     pub async fn register_test_user(conn: &mut DbConn, rng: &mut ThreadRng) -> UserId {
         let (claims, key, key_type) = gen_user(rng);
-        let id = crate::users::dao::register_user(conn, &claims, &key, key_type.clone())
+        let id = crate::users::dao::register_user(conn, &claims, &key, key_type.clone(), None)
             .await
             .unwrap();
         UserId(id)
     }
+
+    /// Start a session for user, returns its bearer authorization header
+    pub async fn auth_header(
+        conn: &mut DbConn,
+        state: &State,
+        user: &UserId,
+    ) -> (header::HeaderName, String) {
+        let session = crate::users::dao::create_session(conn, user, None, LoginMethod::Key)
+            .await
+            .unwrap();
+        let token = issue_access_token(&state.token_keys, &state.id, user, &session).unwrap();
+        (header::AUTHORIZATION, format!("Bearer {}", token))
+    }
 }
@@ -1,23 +1,26 @@
 use std::fmt;
 
-use crate::prelude::*;
-use serde::{Deserialize, Serialize};
+use actix_web::cookie::Key;
+
+use crate::config::Settings;
+use crate::users::auth::TokenKeys;
+use crate::users::mail::MailSender;
+use crate::users::oidc::OidcClient;
+use crate::users::ratelimit::LoginLimiter;
 use sqlx::MySqlPool;
 use uuid::Uuid;
 
-/// Login cookie data
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LoginCookie {
-    pub id: Uuid,
-    pub valid_till: Timestamp,
-    pub key_login: bool,
-}
-
 pub struct State {
-    // pub config: Config,
+    pub config: Settings,
     pub sql: MySqlPool,
     // pub kv: KvPool,
     pub id: Uuid,
+    pub token_keys: TokenKeys,
+    pub login_limiter: LoginLimiter,
+    pub oidc: OidcClient,
+    pub mailer: Box<dyn MailSender>,
+    /// Signs short lived cookies, derived from the session key
+    pub cookie_key: Key,
 }
 
 // required for actix-tracing
@@ -6,6 +6,8 @@ use std::collections::{HashMap, HashSet};
 
 use super::models::*;
 use super::*;
+use crate::lists::history::{self, Action};
+use crate::lists::sorting;
 
 pub async fn update_deleted_lists(
     sql: &mut DbConn,
@@ -72,7 +74,9 @@ async fn _update_deleted_lists(
     for v in data.lists.into_iter() {
         // don't process deletions we already know
         if !return_lists.remove(&v) {
-            let owner = sqlx::query_as::<_, (Uuid,)>("SELECT owner FROM lists WHERE uuid = ?")
+            // lists in the trash are deleted already
+            let sql_owner = "SELECT owner FROM lists WHERE uuid = ? AND deleted IS NULL";
+            let owner = sqlx::query_as::<_, (Uuid,)>(sql_owner)
                 .bind(v)
                 .fetch_optional(&mut *transaction)
                 .await
@@ -113,9 +117,10 @@ async fn _update_deleted_lists(
         .await
         .context("inserting deleted_list_shared")?;
     }
-    // delete list
+    // move list to trash
     for v in filtered.iter() {
-        sqlx::query("DELETE FROM lists WHERE owner = ? AND uuid = ?")
+        sqlx::query("UPDATE lists SET deleted = ? WHERE owner = ? AND uuid = ?")
+            .bind(t_now)
             .bind(user.0)
             .bind(v)
             .execute(&mut *transaction)
@@ -168,12 +173,12 @@ async fn _update_changed_lists(
     };
     let sql_fetch_resp = format!(
         "SELECT -1 as permissions,uuid,name,name_a,name_b,changed,created
-    FROM lists l WHERE owner = ? {time_cond_lists}
+    FROM lists l WHERE owner = ? AND l.deleted IS NULL {time_cond_lists}
     UNION
     SELECT p.write as permissions,uuid,name,name_a,name_b,l.changed,l.created
     FROM lists l
     JOIN list_permissions p ON p.list = l.uuid
-    WHERE p.user = ? {time_cond_shared}
+    WHERE p.user = ? AND l.deleted IS NULL {time_cond_shared}
     ",
         time_cond_lists = time_cond_lists,
         time_cond_shared = time_cond_shared
@@ -204,11 +209,11 @@ async fn _update_changed_lists(
     SELECT 1 FROM deleted_list_shared WHERE list = ? AND user = ?"
     );
     let sql_owner_changed = "SELECT owner,changed FROM lists WHERE uuid = ? FOR UPDATE";
-    let sql_foreign_perm = "SELECT `write` FROM list_permissions WHERE list = ? AND list = ?";
+    let sql_foreign_perm = "SELECT `write` FROM list_permissions WHERE list = ? AND user = ?";
     let query_insert_list = "INSERT INTO lists (uuid,name,name_a,name_b,changed,created,owner)
                 VALUES (?,?,?,?,?,?,?)";
     let query_update_list =
-        "UPDATE lists SET name=?, name_a = ?, name_b = ?, changed = ? WHERE list = ?";
+        "UPDATE lists SET name=?, name_a = ?, name_b = ?, changed = ? WHERE uuid = ?";
     let amount = data.lists.len();
     let mut updated = 0;
     let mut inserted = 0;
@@ -269,6 +274,7 @@ async fn _update_changed_lists(
             // TODO: remove from return lists
 
             // update existing list
+            history::record_list(&mut *transaction, &v.uuid, user, Action::Change).await?;
             sqlx::query(query_update_list)
                 .bind(v.name)
                 .bind(v.name_a)
@@ -293,6 +299,7 @@ async fn _update_changed_lists(
                 .execute(&mut *transaction)
                 .await
                 .context("inserting list")?;
+            history::record_list(&mut *transaction, &v.uuid, user, Action::Create).await?;
             inserted += 1;
         }
     }
@@ -445,6 +452,7 @@ async fn _update_deleted_entries(
         }
 
         // delete entry
+        history::record_entry(&mut *transaction, &e.entry, user, Action::Delete).await?;
         let res = sqlx::query(sqlt_delete_entry)
             .bind(e.entry)
             .execute(&mut *transaction)
@@ -504,13 +512,14 @@ async fn _update_changed_entries(
         ""
     };
     let sql_t = format!(
-        "SELECT e.list,e.uuid,e.changed,tip FROM entries e
+        "SELECT e.list,e.uuid,e.changed,tip,e.created,e.created_by,e.changed_by FROM entries e
     JOIN list_permissions p ON e.list = p.list
-    WHERE p.user = ? {time}
+    JOIN lists l ON e.list = l.uuid
+    WHERE p.user = ? AND l.deleted IS NULL {time}
     UNION
-    SELECT e.list,e.uuid,e.changed,tip FROM entries e
+    SELECT e.list,e.uuid,e.changed,tip,e.created,e.created_by,e.changed_by FROM entries e
     JOIN lists l ON e.list = l.uuid
-    WHERE l.owner = ? {time}",
+    WHERE l.owner = ? AND l.deleted IS NULL {time}",
         time = time_addition
     );
     let q = sqlx::query_as::<_, EntryChangedEntryBlank>(sql_t.as_str());
@@ -535,13 +544,16 @@ async fn _update_changed_entries(
     let mut ignored = Vec::new();
     let mut invalid = Vec::new();
 
-    let sqlt_owner = "SELECT owner FROM lists WHERE uuid = ?";
+    // lists in the trash are treated as not existing
+    let sqlt_owner = "SELECT owner FROM lists WHERE uuid = ? AND deleted IS NULL";
     let sqlt_perms_shared = "SELECT `write` FROM list_permissions WHERE list = ? AND user = ?";
-    let sqlt_update_entry = "UPDATE entries SET tip = ?, changed = ?, updated = ? WHERE uuid = ?";
+    let sqlt_update_entry =
+        "UPDATE entries SET tip = ?, changed = ?, updated = ?, changed_by = ? WHERE uuid = ?";
     let sqlt_entry_deleted = "SELECT 1 FROM deleted_entry WHERE entry = ?";
     let sqlt_entry_changed_date = "SELECT changed FROM entries WHERE uuid = ? FOR UPDATE";
     let sqlt_insert_entry =
-        "INSERT INTO entries (list,uuid,changed,updated,tip) VALUES (?,?,?,?,?)";
+        "INSERT INTO entries (list,uuid,created,changed,updated,tip,created_by,changed_by)
+        VALUES (?,?,?,?,?,?,?,?)";
     let sqlt_delete_meanings = "DELETE FROM entry_meaning WHERE entry = ?";
     let sqlt_insert_meaning = "INSERT INTO entry_meaning (entry,`value`,is_a) VALUES (?,?,?)";
 
@@ -573,7 +585,7 @@ async fn _update_changed_entries(
             None => {
                 let res: Option<Uuid> = sqlx::query_scalar(sqlt_owner)
                     .bind(e.list)
-                    .fetch_one(&mut *transaction)
+                    .fetch_optional(&mut *transaction)
                     .await
                     .context("fetching list owner")?;
                 if let Some(owner) = res {
@@ -619,6 +631,7 @@ async fn _update_changed_entries(
             .fetch_optional(&mut *transaction)
             .await
             .context("fetching changed date")?;
+        let created = res.is_none();
         if let Some(changed) = res {
             // do not take over outdated entries
             if changed >= e.changed {
@@ -626,10 +639,12 @@ async fn _update_changed_entries(
                 ignored.push(e.uuid);
                 continue;
             }
+            history::record_entry(&mut *transaction, &e.uuid, user, Action::Change).await?;
             sqlx::query(sqlt_update_entry)
                 .bind(e.tip)
                 .bind(e.changed)
                 .bind(t_now)
+                .bind(user.0)
                 .bind(e.uuid)
                 .execute(&mut *transaction)
                 .await
@@ -639,9 +654,12 @@ async fn _update_changed_entries(
             sqlx::query(sqlt_insert_entry)
                 .bind(e.list)
                 .bind(e.uuid)
+                .bind(t_now)
                 .bind(e.changed)
                 .bind(t_now)
                 .bind(e.tip)
+                .bind(user.0)
+                .bind(user.0)
                 .execute(&mut *transaction)
                 .await
                 .context("inserting entry")?;
@@ -661,6 +679,10 @@ async fn _update_changed_entries(
                 .await
                 .context("inserting meaning")?;
         }
+        sorting::update_sort_keys(&mut *transaction, &e.uuid).await?;
+        if created {
+            history::record_entry(&mut *transaction, &e.uuid, user, Action::Create).await?;
+        }
     }
 
     // now fetch the meanings of returned delta
@@ -20,8 +20,6 @@ pub enum ListError {
     Serde(#[from] serde_json::error::Error),
     #[error("db error")]
     Sqlx(#[from] sqlx::Error),
-    #[error("invalid or missing auth")]
-    NotAuthenticated,
 }
 
 impl ResponseError for ListError {
@@ -31,7 +29,6 @@ impl ResponseError for ListError {
             ListError::Serde(_) => HttpResponse::BadRequest()
                 .reason("invalid payload")
                 .finish(),
-            ListError::NotAuthenticated => HttpResponse::Unauthorized().finish(),
             e => {
                 error!("{}", e);
                 HttpResponse::InternalServerError().finish()
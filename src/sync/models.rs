@@ -187,6 +187,15 @@ pub struct EntryChangedEntry {
     pub tip: String,
     pub changed: Timestamp,
     pub meanings: Vec<Meaning>,
+    /// Set by the server, ignored when sent
+    #[serde(default)]
+    pub created: Option<Timestamp>,
+    /// Set by the server, ignored when sent
+    #[serde(default)]
+    pub created_by: Option<Uuid>,
+    /// Set by the server, ignored when sent
+    #[serde(default)]
+    pub changed_by: Option<Uuid>,
 }
 
 impl Hash for EntryChangedEntry {
@@ -208,6 +217,9 @@ pub struct EntryChangedEntryBlank {
     pub uuid: Uuid,
     pub changed: Timestamp,
     pub tip: String,
+    pub created: Timestamp,
+    pub created_by: Option<Uuid>,
+    pub changed_by: Option<Uuid>,
 }
 
 impl EntryChangedEntryBlank {
@@ -218,6 +230,9 @@ impl EntryChangedEntryBlank {
             tip: self.tip,
             changed: self.changed,
             meanings,
+            created: Some(self.created),
+            created_by: self.created_by,
+            changed_by: self.changed_by,
         }
     }
 }
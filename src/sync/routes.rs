@@ -1,6 +1,5 @@
 use super::models::*;
 use super::*;
-use actix_identity::Identity;
 use actix_web::{post, web, HttpResponse};
 
 pub fn init(cfg: &mut web::ServiceConfig) {
@@ -10,54 +9,45 @@ pub fn init(cfg: &mut web::ServiceConfig) {
         .service(entry_sync_changed);
 }
 
-#[instrument(skip(id, reg, state))]
+#[instrument(skip(reg, state))]
 #[post("/api/v1/sync/lists/deleted")]
 async fn list_sync_del(
     reg: web::Json<ListDeletedRequest>,
-    id: Identity,
+    user: AuthUser,
     state: AppState,
 ) -> Result<HttpResponse> {
-    let identity = id.identity();
-    trace!(?identity, "list sync deleted request");
-    let user = Uuid::parse_str(&identity.ok_or(ListError::NotAuthenticated)?)?;
+    let user = user.0;
+    trace!(%user, "list sync deleted request");
     let data = reg.into_inner();
 
-    let response =
-        dao::update_deleted_lists(&mut *state.sql.acquire().await?, data, &UserId(user)).await?;
+    let response = dao::update_deleted_lists(&mut *state.sql.acquire().await?, data, &user).await?;
     Ok(HttpResponse::Ok().json(response))
 }
 
-#[instrument(skip(id, reg, state))]
+#[instrument(skip(reg, state))]
 #[post("/api/v1/sync/lists/changed")]
 async fn list_sync_changed(
     reg: web::Json<ListChangedRequest>,
-    id: Identity,
+    user: AuthUser,
     state: AppState,
 ) -> Result<HttpResponse> {
-    let identity = id.identity();
-    trace!(?identity, "list sync changed request");
-    let user = Uuid::parse_str(&identity.ok_or(ListError::NotAuthenticated)?)?;
-    let response = dao::update_changed_lists(
-        &mut *state.sql.acquire().await?,
-        reg.into_inner(),
-        &UserId(user),
-    )
-    .await?;
+    let user = user.0;
+    trace!(%user, "list sync changed request");
+    let response =
+        dao::update_changed_lists(&mut *state.sql.acquire().await?, reg.into_inner(), &user)
+            .await?;
     Ok(HttpResponse::Ok().json(response))
 }
 
-#[instrument(skip(id, reg, state))]
+#[instrument(skip(reg, state))]
 #[post("/api/v1/sync/entries/deleted")]
 async fn entry_sync_del(
     reg: web::Json<EntryDeletedRequest>,
-    id: Identity,
+    user: AuthUser,
     state: AppState,
 ) -> Result<HttpResponse> {
-    let identity = id.identity();
-    trace!(?identity, "entry sync deleted request");
-    let user = UserId(Uuid::parse_str(
-        &identity.ok_or(ListError::NotAuthenticated)?,
-    )?);
+    let user = user.0;
+    trace!(%user, "entry sync deleted request");
     let data = reg.into_inner();
 
     let response =
@@ -65,18 +55,15 @@ async fn entry_sync_del(
     Ok(HttpResponse::Ok().json(response))
 }
 
-#[instrument(skip(id, reg, state))]
+#[instrument(skip(reg, state))]
 #[post("/api/v1/sync/entries/changed")]
 async fn entry_sync_changed(
     reg: web::Json<EntryChangedRequest>,
-    id: Identity,
+    user: AuthUser,
     state: AppState,
 ) -> Result<HttpResponse> {
-    let identity = id.identity();
-    trace!(?identity, "entry sync changed request");
-    let user = UserId(Uuid::parse_str(
-        &identity.ok_or(ListError::NotAuthenticated)?,
-    )?);
+    let user = user.0;
+    trace!(%user, "entry sync changed request");
     let data = reg.into_inner();
 
     //let mut connection = state.sql.acquire().await?
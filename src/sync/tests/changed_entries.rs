@@ -69,6 +69,9 @@ async fn test_basic_changed_entries() {
             .get(&e_exp.uuid)
             .expect("inserted entry not found");
         assert_entry_eq(e_res, e_exp);
+        assert_eq!(Some(user.0), e_res.created_by);
+        assert_eq!(Some(user.0), e_res.changed_by);
+        assert!(e_res.created.is_some());
     }
     for e_exp in entries2.iter() {
         // verify we've insertes entries2
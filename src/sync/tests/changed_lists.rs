@@ -112,6 +112,77 @@ async fn test_basic_changed_lists() {
     db.drop_async().await;
 }
 
+#[actix_rt::test]
+async fn test_changed_own_list() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let user = register_test_user(conn, &mut rng).await;
+    let list = gen_list(None);
+    insert_list(conn, &user, &list).await;
+
+    // renaming an existing list updates it in place
+    let mut changed = list.clone();
+    changed.name = random_string(&mut rng, 7);
+    changed.changed = Utc::now().naive_utc();
+    let change_req = ListChangedRequest {
+        since: None,
+        lists: vec![changed.clone()],
+    };
+    let res = dao::update_changed_lists(conn, change_req, &user)
+        .await
+        .unwrap();
+    assert_eq!(0, res.failures.len());
+    let change_empty = ListChangedRequest {
+        since: None,
+        lists: vec![],
+    };
+    let res = dao::update_changed_lists(conn, change_empty, &user)
+        .await
+        .unwrap();
+    assert_eq!(1, res.delta.len());
+    assert_eq!(changed.name, res.delta[&list.uuid].name);
+
+    db.drop_async().await;
+}
+
+#[actix_rt::test]
+async fn test_changed_shared_list() {
+    let db = DatabaseGuard::new().await;
+    let conn = &mut *db.conn().await;
+    let mut rng = rand::thread_rng();
+
+    let owner = register_test_user(conn, &mut rng).await;
+    let writer = register_test_user(conn, &mut rng).await;
+    let list = gen_list(None);
+    insert_list(conn, &owner, &list).await;
+    insert_list_perm(conn, &writer, &list.uuid, true, false).await;
+
+    // shared users with write permission change the list itself
+    let mut changed = list.clone();
+    changed.name = random_string(&mut rng, 7);
+    changed.changed = Utc::now().naive_utc();
+    let change_req = ListChangedRequest {
+        since: None,
+        lists: vec![changed.clone()],
+    };
+    let res = dao::update_changed_lists(conn, change_req, &writer)
+        .await
+        .unwrap();
+    assert_eq!(0, res.failures.len());
+    let change_empty = ListChangedRequest {
+        since: None,
+        lists: vec![],
+    };
+    let res = dao::update_changed_lists(conn, change_empty, &owner)
+        .await
+        .unwrap();
+    assert_eq!(changed.name, res.delta[&list.uuid].name);
+
+    db.drop_async().await;
+}
+
 fn assert_list_eq(
     recv: &ListChangedEntryRecv,
     send: &ListChangedEntrySend,
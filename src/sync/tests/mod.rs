@@ -111,5 +111,8 @@ fn gen_entry(list: &Uuid, date: Option<NaiveDateTime>) -> EntryChangedEntry {
         list: list.clone(),
         tip: random_string(&mut rng, 7),
         meanings: v,
+        created: None,
+        created_by: None,
+        changed_by: None,
     }
 }
@@ -0,0 +1,225 @@
+use actix_identity::RequestIdentity;
+use actix_web::{dev::Payload, http::header, FromRequest, HttpRequest};
+use base64ct::{Base64Url, Encoding};
+use chrono::{Duration, Utc};
+use futures::future::LocalBoxFuture;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use super::*;
+
+/// Lifetime of access tokens in seconds
+pub const ACCESS_TOKEN_LIFETIME: i64 = 15 * 60;
+/// Lifetime of refresh tokens in days
+pub const REFRESH_TOKEN_LIFETIME: i64 = 30;
+/// Lifetime of TOTP login challenges in seconds
+pub const TOTP_CHALLENGE_LIFETIME: i64 = 5 * 60;
+/// Lifetime of email change confirmations in hours
+pub const EMAIL_CHANGE_LIFETIME: i64 = 24;
+/// Sessions unused for this many days are expired
+pub const SESSION_IDLE_LIFETIME: i64 = 30;
+/// Sessions count as fresh login for this many seconds, for accounts without other proofs
+pub const FRESH_LOGIN_LIFETIME: i64 = 5 * 60;
+
+/// Authenticated user and its session, extracted from the identity cookie or an `Authorization: Bearer` access token
+#[derive(Debug)]
+pub struct AuthUser(pub UserId, pub Uuid);
+
+impl FromRequest for AuthUser {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { auth_user(&req).await })
+    }
+}
+
+async fn auth_user(req: &HttpRequest) -> Result<AuthUser> {
+    let state = req
+        .app_data::<AppState>()
+        .ok_or_else(|| color_eyre::eyre::eyre!("missing app state"))?;
+    let session = if let Some(auth) = req.headers().get(header::AUTHORIZATION) {
+        let token = auth
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(AuthError::NotAuthenticated)?;
+        verify_access_token(&state.token_keys, &state.id, token)?.1
+    } else {
+        let identity = req.get_identity().ok_or(AuthError::NotAuthenticated)?;
+        Uuid::parse_str(&identity).map_err(|_| AuthError::NotAuthenticated)?
+    };
+    // sessions can be revoked at any time, so always check them
+    match dao::session_user(&mut *state.sql.acquire().await?, &session).await? {
+        Some(user) => Ok(AuthUser(user, session)),
+        None => {
+            trace!(%session, "unknown or expired session");
+            Err(AuthError::NotAuthenticated)
+        }
+    }
+}
+
+/// Signing keys for access tokens
+pub struct TokenKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+impl TokenKeys {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum TokenType {
+    Access,
+    /// password verified, second factor missing
+    Totp,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AccessClaims {
+    sub: Uuid,
+    aud: String,
+    iat: i64,
+    exp: i64,
+    typ: TokenType,
+    /// Session the token got issued for
+    sid: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChallengeClaims {
+    sub: Uuid,
+    aud: String,
+    iat: i64,
+    exp: i64,
+    typ: TokenType,
+    /// device label for the session to create
+    device: Option<String>,
+}
+
+/// Token pair returned on token login
+#[derive(Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    /// Access token lifetime in seconds
+    pub expires_in: i64,
+    pub refresh_token: String,
+}
+
+/// Create signed access token for a user session
+pub fn issue_access_token(
+    keys: &TokenKeys,
+    server_id: &Uuid,
+    user: &UserId,
+    session: &Uuid,
+) -> Result<String> {
+    let t_now = Utc::now().timestamp();
+    let claims = AccessClaims {
+        sub: user.0,
+        aud: server_id.to_string(),
+        iat: t_now,
+        exp: t_now + ACCESS_TOKEN_LIFETIME,
+        typ: TokenType::Access,
+        sid: *session,
+    };
+    Ok(encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &keys.encoding,
+    )?)
+}
+
+/// Verify access token, returns the user and session it got issued for
+pub fn verify_access_token(
+    keys: &TokenKeys,
+    server_id: &Uuid,
+    token: &str,
+) -> Result<(UserId, Uuid)> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_audience(&[server_id]);
+    validation.leeway = 5;
+    let td = decode::<AccessClaims>(token, &keys.decoding, &validation).map_err(|e| {
+        debug!(?e, "invalid access token");
+        AuthError::NotAuthenticated
+    })?;
+    if td.claims.typ != TokenType::Access {
+        return Err(AuthError::NotAuthenticated);
+    }
+    Ok((UserId(td.claims.sub), td.claims.sid))
+}
+
+/// Create signed challenge for the TOTP login step, after the password got verified
+pub fn issue_totp_challenge(
+    keys: &TokenKeys,
+    server_id: &Uuid,
+    user: &UserId,
+    device: Option<String>,
+) -> Result<String> {
+    let t_now = Utc::now().timestamp();
+    let claims = ChallengeClaims {
+        sub: user.0,
+        aud: server_id.to_string(),
+        iat: t_now,
+        exp: t_now + TOTP_CHALLENGE_LIFETIME,
+        typ: TokenType::Totp,
+        device,
+    };
+    Ok(encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &keys.encoding,
+    )?)
+}
+
+/// Verify TOTP login challenge, returns the user and device label
+pub fn verify_totp_challenge(
+    keys: &TokenKeys,
+    server_id: &Uuid,
+    challenge: &str,
+) -> Result<(UserId, Option<String>)> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_audience(&[server_id]);
+    let td = decode::<ChallengeClaims>(challenge, &keys.decoding, &validation).map_err(|e| {
+        debug!(?e, "invalid totp challenge");
+        AuthError::InvalidCredentials
+    })?;
+    if td.claims.typ != TokenType::Totp {
+        return Err(AuthError::InvalidCredentials);
+    }
+    Ok((UserId(td.claims.sub), td.claims.device))
+}
+
+/// Generate new random token for refresh tokens or email confirmation.
+/// Returns the token and its hash for storage.
+pub fn generate_token() -> (String, Vec<u8>) {
+    let mut token = [0u8; 32];
+    OsRng.fill_bytes(&mut token);
+    let hash = Sha256::digest(token).to_vec();
+    (Base64Url::encode_string(&token), hash)
+}
+
+/// Hash of a client supplied token
+pub fn token_hash(token: &str) -> Result<Vec<u8>> {
+    let decoded = Base64Url::decode_vec(token).map_err(|_| AuthError::InvalidCredentials)?;
+    Ok(Sha256::digest(decoded).to_vec())
+}
+
+/// Expiry of a refresh token created now
+pub fn refresh_token_expiry() -> Timestamp {
+    Utc::now().naive_utc() + Duration::days(REFRESH_TOKEN_LIFETIME)
+}
+
+/// Expiry of an email change created now
+pub fn email_change_expiry() -> Timestamp {
+    Utc::now().naive_utc() + Duration::hours(EMAIL_CHANGE_LIFETIME)
+}
@@ -1,26 +1,36 @@
 use std::convert::TryInto;
 use std::str::FromStr;
 
-use chrono::Utc;
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
 use sqlx::Connection;
 use sqlx::MySqlConnection;
 
+use super::auth::SESSION_IDLE_LIFETIME;
 use super::user::*;
 use super::AuthError;
 use super::Result;
+use crate::audit::{AuditLog, Event};
 use crate::prelude::*;
 
 // no async traits and I'd like to avoid async_trait
+/// Register user with key. `nonce` (jti, expiry) of the register proof is consumed in the same
+/// transaction, so a failed registration doesn't spend it.
 #[instrument]
 pub async fn register_user(
     sql: &mut MySqlConnection,
     claims: &RegisterClaims,
     auth_key: &[u8],
     key_type: KeyType,
+    nonce: Option<(&str, Timestamp)>,
 ) -> Result<Uuid> {
     let mut transaction = sql.begin().await?;
     let t_now = Utc::now().naive_utc();
 
+    if let Some((jti, expires)) = nonce {
+        use_proof_nonce(&mut transaction, &UserId(claims.iss), jti, expires).await?;
+    }
+
     let locked: Option<String> = None;
     let sql_user = "INSERT INTO users (uuid,name,locked,last_seen,delete_after) VALUES(?,?,?,?,?)";
     let res = sqlx::query(sql_user)
@@ -73,6 +83,208 @@ pub async fn user_key(sql: &mut MySqlConnection, user: &UserId) -> Result<Option
     }
 }
 
+/// Consume nonce of a key signed proof. Returns [AuthError::ReplayedProof] if already used.
+///
+/// `expires` has to be after the last moment the proof is accepted.
+pub async fn use_proof_nonce(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    jti: &str,
+    expires: Timestamp,
+) -> Result<()> {
+    let t_now = Utc::now().naive_utc();
+    // nonces of expired proofs aren't required anymore
+    let res = sqlx::query("DELETE FROM proof_nonce WHERE expires < ?")
+        .bind(t_now)
+        .execute(&mut *sql)
+        .await?;
+    trace!(affected = res.rows_affected(), "removed expired nonces");
+
+    let res = sqlx::query("INSERT INTO proof_nonce (user_id,jti,expires) VALUES(?,?,?)")
+        .bind(user.0)
+        .bind(jti)
+        .bind(expires)
+        .execute(&mut *sql)
+        .await;
+    if check_duplicate(res)? {
+        return Err(AuthError::ReplayedProof);
+    }
+    Ok(())
+}
+
+/// Maximum length of session device labels, see user_session table
+const MAX_DEVICE_LENGTH: usize = 127;
+
+/// Create new login session, returns the session id
+pub async fn create_session(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    device: Option<&str>,
+    method: LoginMethod,
+) -> Result<Uuid> {
+    let t_now = Utc::now().naive_utc();
+    // logging in restores accounts pending deletion
+    if cancel_deletion(&mut *sql, user).await? {
+        debug!(%user, "restored account pending deletion");
+        AuditLog::new(user, Event::DeletionCanceled)
+            .detail(method.as_ref())
+            .write(&mut *sql)
+            .await?;
+    }
+    // cleanup expired sessions of this user
+    let res = sqlx::query("DELETE FROM user_session WHERE user_id = ? AND last_used < ?")
+        .bind(user.0)
+        .bind(t_now - Duration::days(SESSION_IDLE_LIFETIME))
+        .execute(&mut *sql)
+        .await?;
+    trace!(affected = res.rows_affected(), "removed expired sessions");
+
+    let session = Uuid::new_v4();
+    let device: Option<String> = device.map(|v| v.chars().take(MAX_DEVICE_LENGTH).collect());
+    let sql_insert = "INSERT INTO user_session (uuid,user_id,device,created,last_used,method)
+    VALUES(?,?,?,?,?,?)";
+    sqlx::query(sql_insert)
+        .bind(session)
+        .bind(user.0)
+        .bind(device)
+        .bind(t_now)
+        .bind(t_now)
+        .bind(method.as_ref())
+        .execute(&mut *sql)
+        .await?;
+    Ok(session)
+}
+
+/// Resolve session to its user and mark it as used. Returns None for unknown or expired sessions.
+pub async fn session_user(sql: &mut MySqlConnection, session: &Uuid) -> Result<Option<UserId>> {
+    let t_now = Utc::now().naive_utc();
+    let res = sqlx::query_as::<_, (Uuid, Timestamp)>(
+        "SELECT user_id,last_used FROM user_session WHERE uuid = ?",
+    )
+    .bind(session)
+    .fetch_optional(&mut *sql)
+    .await?;
+    let (user, last_used) = match res {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    if last_used < t_now - Duration::days(SESSION_IDLE_LIFETIME) {
+        trace!(%session, "session expired");
+        return Ok(None);
+    }
+    // avoid a write on every request
+    if t_now - last_used > Duration::minutes(1) {
+        sqlx::query("UPDATE user_session SET last_used = ? WHERE uuid = ?")
+            .bind(t_now)
+            .bind(session)
+            .execute(&mut *sql)
+            .await?;
+    }
+    Ok(Some(UserId(user)))
+}
+
+/// All sessions of a user, most recently used first
+pub async fn sessions(sql: &mut MySqlConnection, user: &UserId) -> Result<Vec<Session>> {
+    let sql_fetch = "SELECT uuid,device,created,last_used,method FROM user_session
+    WHERE user_id = ? ORDER BY last_used DESC";
+    let sessions = sqlx::query_as::<_, Session>(sql_fetch)
+        .bind(user.0)
+        .fetch_all(sql)
+        .await?;
+    Ok(sessions)
+}
+
+/// Revoke session of user, returns false if not found
+pub async fn revoke_session(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    session: &Uuid,
+) -> Result<bool> {
+    let res = sqlx::query("DELETE FROM user_session WHERE uuid = ? AND user_id = ?")
+        .bind(session)
+        .bind(user.0)
+        .execute(sql)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Revoke all sessions of user, optionally except one. Returns the amount of revoked sessions.
+pub async fn revoke_sessions(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    except: Option<&Uuid>,
+) -> Result<u64> {
+    let res = match except {
+        Some(session) => {
+            sqlx::query("DELETE FROM user_session WHERE user_id = ? AND uuid != ?")
+                .bind(user.0)
+                .bind(session)
+                .execute(sql)
+                .await?
+        }
+        None => {
+            sqlx::query("DELETE FROM user_session WHERE user_id = ?")
+                .bind(user.0)
+                .execute(sql)
+                .await?
+        }
+    };
+    trace!(%user, affected = res.rows_affected(), "revoked sessions");
+    Ok(res.rows_affected())
+}
+
+/// Store hash of new refresh token for a session
+pub async fn create_refresh_token(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    session: &Uuid,
+    token_hash: &[u8],
+    expires: Timestamp,
+) -> Result<()> {
+    let t_now = Utc::now().naive_utc();
+    let sql_insert = "INSERT INTO refresh_token (token_hash,user_id,session,created,expires)
+    VALUES(?,?,?,?,?)";
+    sqlx::query(sql_insert)
+        .bind(token_hash)
+        .bind(user.0)
+        .bind(session)
+        .bind(t_now)
+        .bind(expires)
+        .execute(sql)
+        .await?;
+    Ok(())
+}
+
+/// Consume refresh token, returns the user and session if valid. Tokens are single use.
+pub async fn use_refresh_token(
+    sql: &mut MySqlConnection,
+    token_hash: &[u8],
+) -> Result<Option<(UserId, Uuid)>> {
+    let t_now = Utc::now().naive_utc();
+    let mut transaction = sql.begin().await?;
+    let res = sqlx::query_as::<_, (Uuid, Uuid, Timestamp)>(
+        "SELECT user_id,session,expires FROM refresh_token WHERE token_hash = ? FOR UPDATE",
+    )
+    .bind(token_hash)
+    .fetch_optional(&mut transaction)
+    .await?;
+    let (user, session, expires) = match res {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    sqlx::query("DELETE FROM refresh_token WHERE token_hash = ? OR expires < ?")
+        .bind(token_hash)
+        .bind(t_now)
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    if expires < t_now {
+        trace!(%user, "refresh token expired");
+        return Ok(None);
+    }
+    Ok(Some((UserId(user), session)))
+}
+
 /// Returns true if user got deleted
 pub async fn user_deleted(sql: &mut MySqlConnection, user: &UserId) -> Result<bool> {
     let res = sqlx::query_as::<_, (bool,)>("SELECT 1 FROM deleted_user WHERE user = ?")
@@ -114,6 +326,212 @@ pub async fn create_password_login(
     Ok(())
 }
 
+/// Replace password hash of user
+pub async fn update_password(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    password: &str,
+) -> Result<()> {
+    let res = sqlx::query("UPDATE user_login SET password = ? WHERE user_id = ?")
+        .bind(password)
+        .bind(user.0)
+        .execute(sql)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(AuthError::NoPasswordLogin);
+    }
+    Ok(())
+}
+
+/// Store pending email change, replacing any previous one of the user.
+/// The current email stays valid until [confirm_email_change].
+pub async fn create_email_change(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    email: &str,
+    token_hash: &[u8],
+    expires: Timestamp,
+) -> Result<()> {
+    let t_now = Utc::now().naive_utc();
+    // fail early, confirmation re-checks this
+    if user_by_email(&mut *sql, email).await?.is_some() {
+        return Err(AuthError::ExistingLogin);
+    }
+    let sql_insert = "REPLACE INTO email_change (user_id,email,token_hash,created,expires)
+    VALUES(?,?,?,?,?)";
+    sqlx::query(sql_insert)
+        .bind(user.0)
+        .bind(email)
+        .bind(token_hash)
+        .bind(t_now)
+        .bind(expires)
+        .execute(sql)
+        .await?;
+    Ok(())
+}
+
+/// Apply pending email change, marks the new email as verified.
+/// Returns the user, None for unknown or expired tokens.
+pub async fn confirm_email_change(
+    sql: &mut MySqlConnection,
+    token_hash: &[u8],
+) -> Result<Option<UserId>> {
+    let t_now = Utc::now().naive_utc();
+    let mut transaction = sql.begin().await?;
+    let res = sqlx::query_as::<_, (Uuid, String, Timestamp)>(
+        "SELECT user_id,email,expires FROM email_change WHERE token_hash = ? FOR UPDATE",
+    )
+    .bind(token_hash)
+    .fetch_optional(&mut transaction)
+    .await?;
+    let (user, email, expires) = match res {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    sqlx::query("DELETE FROM email_change WHERE token_hash = ? OR expires < ?")
+        .bind(token_hash)
+        .bind(t_now)
+        .execute(&mut transaction)
+        .await?;
+    if expires < t_now {
+        transaction.commit().await?;
+        trace!(%user, "email change expired");
+        return Ok(None);
+    }
+    let res = sqlx::query("UPDATE user_login SET email = ?, verified = TRUE WHERE user_id = ?")
+        .bind(&email)
+        .bind(user)
+        .execute(&mut transaction)
+        .await;
+    if check_duplicate(res)? {
+        return Err(AuthError::ExistingLogin);
+    }
+    transaction.commit().await?;
+    Ok(Some(UserId(user)))
+}
+
+/// Maximum length of user names, see users table
+pub const MAX_NAME_LENGTH: usize = 60;
+
+/// Store started OIDC login flow by its state hash
+#[allow(clippy::too_many_arguments)]
+pub async fn create_oidc_flow(
+    sql: &mut MySqlConnection,
+    state_hash: &[u8],
+    provider: &str,
+    nonce: &str,
+    verifier: &str,
+    link_user: Option<&UserId>,
+    device: Option<&str>,
+    expires: Timestamp,
+) -> Result<()> {
+    let device: Option<String> = device.map(|v| v.chars().take(MAX_DEVICE_LENGTH).collect());
+    let sql_insert =
+        "INSERT INTO oidc_flow (state_hash,provider,nonce,verifier,link_user,device,expires)
+    VALUES(?,?,?,?,?,?,?)";
+    sqlx::query(sql_insert)
+        .bind(state_hash)
+        .bind(provider)
+        .bind(nonce)
+        .bind(verifier)
+        .bind(link_user.map(|v| v.0))
+        .bind(device)
+        .bind(expires)
+        .execute(sql)
+        .await?;
+    Ok(())
+}
+
+/// Consume OIDC login flow, returns None for unknown or expired flows
+pub async fn take_oidc_flow(
+    sql: &mut MySqlConnection,
+    state_hash: &[u8],
+) -> Result<Option<OidcFlow>> {
+    let t_now = Utc::now().naive_utc();
+    let mut transaction = sql.begin().await?;
+    let sql_fetch = "SELECT provider,nonce,verifier,link_user,device,expires FROM oidc_flow
+    WHERE state_hash = ? FOR UPDATE";
+    let flow = sqlx::query_as::<_, OidcFlow>(sql_fetch)
+        .bind(state_hash)
+        .fetch_optional(&mut transaction)
+        .await?;
+    let res = sqlx::query("DELETE FROM oidc_flow WHERE state_hash = ? OR expires < ?")
+        .bind(state_hash)
+        .bind(t_now)
+        .execute(&mut transaction)
+        .await?;
+    trace!(affected = res.rows_affected(), "removed oidc flows");
+    transaction.commit().await?;
+    Ok(flow.filter(|v| v.expires >= t_now))
+}
+
+/// User linked to the external subject
+pub async fn oidc_user(
+    sql: &mut MySqlConnection,
+    provider: &str,
+    subject: &str,
+) -> Result<Option<UserId>> {
+    let res = sqlx::query_as::<_, (Uuid,)>(
+        "SELECT user_id FROM user_oidc WHERE provider = ? AND subject = ?",
+    )
+    .bind(provider)
+    .bind(subject)
+    .fetch_optional(sql)
+    .await?;
+    Ok(res.map(|(v,)| UserId(v)))
+}
+
+/// Link external subject to user, fails if linked to another user
+pub async fn link_oidc(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    provider: &str,
+    subject: &str,
+) -> Result<()> {
+    let t_now = Utc::now().naive_utc();
+    let res =
+        sqlx::query("INSERT INTO user_oidc (provider,subject,user_id,created) VALUES(?,?,?,?)")
+            .bind(provider)
+            .bind(subject)
+            .bind(user.0)
+            .bind(t_now)
+            .execute(&mut *sql)
+            .await;
+    if check_duplicate(res)? {
+        return match oidc_user(sql, provider, subject).await? {
+            Some(v) if v.0 == user.0 => Ok(()),
+            _ => Err(AuthError::ExistingLogin),
+        };
+    }
+    Ok(())
+}
+
+/// Create new user for an external subject
+pub async fn register_oidc_user(
+    sql: &mut MySqlConnection,
+    provider: &str,
+    subject: &str,
+    name: &str,
+) -> Result<UserId> {
+    let t_now = Utc::now().naive_utc();
+    let user = UserId(Uuid::new_v4());
+    let name: String = name.chars().take(MAX_NAME_LENGTH).collect();
+    let mut transaction = sql.begin().await?;
+    let sql_user = "INSERT INTO users (uuid,name,locked,last_seen,delete_after) VALUES(?,?,?,?,?)";
+    sqlx::query(sql_user)
+        .bind(user.0)
+        .bind(name)
+        .bind(Option::<String>::None)
+        .bind(t_now)
+        .bind(Option::<u32>::None)
+        .execute(&mut transaction)
+        .await?;
+    link_oidc(&mut transaction, &user, provider, subject).await?;
+    transaction.commit().await?;
+    trace!(%user, provider, "registered oidc user");
+    Ok(user)
+}
+
 /// Retrieve User by uuid
 pub async fn user_by_uuid(sql: &mut MySqlConnection, user: &UserId) -> Result<Option<User>> {
     let sql_fetch = "SELECT uuid,name,locked,last_seen,delete_after FROM users WHERE uuid = ?";
@@ -124,6 +542,97 @@ pub async fn user_by_uuid(sql: &mut MySqlConnection, user: &UserId) -> Result<Op
     Ok(user)
 }
 
+/// Update name and automatic deletion of the user, `None` keeps the current value
+pub async fn update_user(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    name: Option<&str>,
+    delete_after: Option<Option<u32>>,
+) -> Result<()> {
+    let sql_update = "UPDATE users SET name = COALESCE(?,name),
+        delete_after = IF(?,?,delete_after) WHERE uuid = ?";
+    let res = sqlx::query(sql_update)
+        .bind(name)
+        .bind(delete_after.is_some())
+        .bind(delete_after.flatten())
+        .bind(user.0)
+        .execute(sql)
+        .await?;
+    trace!(affected = res.rows_affected(), "update user");
+    Ok(())
+}
+
+/// Schedule deletion of the user after the grace period, ends all sessions.
+/// Returns the time of deletion, an earlier request is kept.
+pub async fn schedule_deletion(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    grace_period: Duration,
+) -> Result<Timestamp> {
+    let t_now = Utc::now().naive_utc();
+    let mut transaction = sql.begin().await?;
+    let sql_insert = "INSERT INTO pending_deletion (user_id,requested,delete_at) VALUES(?,?,?)
+        ON DUPLICATE KEY UPDATE user_id = user_id";
+    sqlx::query(sql_insert)
+        .bind(user.0)
+        .bind(t_now)
+        .bind(t_now + grace_period)
+        .execute(&mut transaction)
+        .await?;
+    let (delete_at,) = sqlx::query_as::<_, (Timestamp,)>(
+        "SELECT delete_at FROM pending_deletion WHERE user_id = ?",
+    )
+    .bind(user.0)
+    .fetch_one(&mut transaction)
+    .await?;
+    revoke_sessions(&mut transaction, user, None).await?;
+    transaction.commit().await?;
+    trace!(%user, %delete_at, "scheduled account deletion");
+    Ok(delete_at)
+}
+
+/// Cancel pending deletion, returns false if none was scheduled
+pub async fn cancel_deletion(sql: &mut MySqlConnection, user: &UserId) -> Result<bool> {
+    let res = sqlx::query("DELETE FROM pending_deletion WHERE user_id = ?")
+        .bind(user.0)
+        .execute(sql)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Users whose grace period is over
+pub async fn due_deletions(sql: &mut MySqlConnection) -> Result<Vec<UserId>> {
+    let t_now = Utc::now().naive_utc();
+    let users: Vec<Uuid> =
+        sqlx::query_scalar::<_, Uuid>("SELECT user_id FROM pending_deletion WHERE delete_at <= ?")
+            .bind(t_now)
+            .fetch(sql)
+            .try_collect()
+            .await?;
+    Ok(users.into_iter().map(UserId).collect())
+}
+
+/// Delete user if the deletion is still pending and due.
+/// Returns false if it got restored in between.
+pub async fn finalize_deletion(sql: &mut MySqlConnection, user: &UserId) -> Result<bool> {
+    let t_now = Utc::now().naive_utc();
+    let mut transaction = sql.begin().await?;
+    let due = sqlx::query_as::<_, (bool,)>(
+        "SELECT 1 FROM pending_deletion WHERE user_id = ? AND delete_at <= ? FOR UPDATE",
+    )
+    .bind(user.0)
+    .bind(t_now)
+    .fetch_optional(&mut transaction)
+    .await?
+    .is_some();
+    if !due {
+        return Ok(false);
+    }
+    delete_user(&mut transaction, user).await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
 /// Delete user account
 pub async fn delete_user(sql: &mut MySqlConnection, user: &UserId) -> Result<()> {
     let t_now = Utc::now().naive_utc();
@@ -154,7 +663,7 @@ pub async fn delete_user(sql: &mut MySqlConnection, user: &UserId) -> Result<()>
     let sql_lists_shared = "INSERT INTO deleted_list_shared (user,list,created)
     SELECT user,list,? FROM list_permissions lp
     JOIN lists l ON lp.list = l.uuid
-    WHERE l.owner = ?";
+    WHERE l.owner = ? AND l.deleted IS NULL";
     let res = sqlx::query(sql_lists_shared)
         .bind(t_now)
         .bind(&user.0)
@@ -171,6 +680,9 @@ pub async fn delete_user(sql: &mut MySqlConnection, user: &UserId) -> Result<()>
         .execute(&mut transaction)
         .await?;
     trace!(user=%user,affected=res.rows_affected(),"deleting user");
+    AuditLog::new(user, Event::AccountDeleted)
+        .write(&mut transaction)
+        .await?;
 
     transaction.commit().await?;
 
@@ -211,3 +723,126 @@ pub async fn update_last_seen(
         .context("updating last seen time")?;
     Ok(())
 }
+
+/// Password login of a user
+pub async fn user_login(sql: &mut MySqlConnection, user: &UserId) -> Result<Option<UserLogin>> {
+    let sql_fetch = "SELECT user_id,email,password,verified FROM user_login WHERE user_id = ?";
+    let res = sqlx::query_as::<_, UserLogin>(sql_fetch)
+        .bind(user.0)
+        .fetch_optional(sql)
+        .await?;
+    Ok(res)
+}
+
+/// TOTP state of user, None if never enrolled
+pub async fn totp(sql: &mut MySqlConnection, user: &UserId) -> Result<Option<UserTotp>> {
+    let res = sqlx::query_as::<_, UserTotp>(
+        "SELECT secret,confirmed,last_step FROM user_totp WHERE user_id = ?",
+    )
+    .bind(user.0)
+    .fetch_optional(sql)
+    .await?;
+    Ok(res)
+}
+
+/// Start TOTP enrollment, replaces unconfirmed enrollments
+pub async fn totp_enroll(sql: &mut MySqlConnection, user: &UserId, secret: &[u8]) -> Result<()> {
+    let t_now = Utc::now().naive_utc();
+    let mut transaction = sql.begin().await?;
+    let confirmed = sqlx::query_as::<_, (bool,)>(
+        "SELECT confirmed FROM user_totp WHERE user_id = ? FOR UPDATE",
+    )
+    .bind(user.0)
+    .fetch_optional(&mut transaction)
+    .await?;
+    if let Some((true,)) = confirmed {
+        return Err(AuthError::TotpEnabled);
+    }
+    let sql_insert =
+        "REPLACE INTO user_totp (user_id,secret,confirmed,created) VALUES(?,?,FALSE,?)";
+    sqlx::query(sql_insert)
+        .bind(user.0)
+        .bind(secret)
+        .bind(t_now)
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Confirm TOTP enrollment with the step of the first code, replacing all recovery codes
+pub async fn totp_confirm(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    step: i64,
+    recovery_hashes: &[Vec<u8>],
+) -> Result<()> {
+    let mut transaction = sql.begin().await?;
+    let sql_confirm =
+        "UPDATE user_totp SET confirmed = TRUE, last_step = ? WHERE user_id = ? AND NOT confirmed";
+    let res = sqlx::query(sql_confirm)
+        .bind(step)
+        .bind(user.0)
+        .execute(&mut transaction)
+        .await?;
+    if res.rows_affected() != 1 {
+        return Err(AuthError::TotpNotEnrolled);
+    }
+    sqlx::query("DELETE FROM totp_recovery WHERE user_id = ?")
+        .bind(user.0)
+        .execute(&mut transaction)
+        .await?;
+    for hash in recovery_hashes {
+        sqlx::query("INSERT INTO totp_recovery (user_id,code_hash) VALUES(?,?)")
+            .bind(user.0)
+            .bind(hash)
+            .execute(&mut transaction)
+            .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Mark TOTP step as used, returns false if it or a later one was already used
+pub async fn totp_use_step(sql: &mut MySqlConnection, user: &UserId, step: i64) -> Result<bool> {
+    let sql_update = "UPDATE user_totp SET last_step = ?
+    WHERE user_id = ? AND confirmed AND (last_step IS NULL OR last_step < ?)";
+    let res = sqlx::query(sql_update)
+        .bind(step)
+        .bind(user.0)
+        .bind(step)
+        .execute(sql)
+        .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// Consume recovery code, returns false if unknown
+pub async fn use_recovery_code(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    code_hash: &[u8],
+) -> Result<bool> {
+    let res = sqlx::query("DELETE FROM totp_recovery WHERE user_id = ? AND code_hash = ?")
+        .bind(user.0)
+        .bind(code_hash)
+        .execute(sql)
+        .await?;
+    trace!(%user, affected = res.rows_affected(), "used recovery code");
+    Ok(res.rows_affected() == 1)
+}
+
+/// Remove TOTP and recovery codes of user
+pub async fn totp_disable(sql: &mut MySqlConnection, user: &UserId) -> Result<()> {
+    let mut transaction = sql.begin().await?;
+    sqlx::query("DELETE FROM totp_recovery WHERE user_id = ?")
+        .bind(user.0)
+        .execute(&mut transaction)
+        .await?;
+    let res = sqlx::query("DELETE FROM user_totp WHERE user_id = ?")
+        .bind(user.0)
+        .execute(&mut transaction)
+        .await?;
+    trace!(%user, affected = res.rows_affected(), "disabled totp");
+    transaction.commit().await?;
+    Ok(())
+}
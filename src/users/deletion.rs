@@ -0,0 +1,33 @@
+//! Background job finalizing account deletions after their grace period
+use std::time::Duration;
+
+use sqlx::MySqlPool;
+
+use super::*;
+
+/// Run [finalize_due] every `interval` on the current runtime
+pub fn spawn_finalizer(pool: MySqlPool, interval: Duration) {
+    actix_rt::spawn(async move {
+        let mut timer = actix_rt::time::interval(interval);
+        loop {
+            timer.tick().await;
+            if let Err(e) = finalize_due(&pool).await {
+                warn!(?e, "finalizing account deletions failed");
+            }
+        }
+    });
+}
+
+/// Delete all accounts whose grace period is over, returns the amount deleted
+pub async fn finalize_due(pool: &MySqlPool) -> Result<usize> {
+    let mut conn = pool.acquire().await?;
+    let mut deleted = 0;
+    for user in dao::due_deletions(&mut conn).await? {
+        // restored by a login since fetching
+        if dao::finalize_deletion(&mut conn, &user).await? {
+            info!(%user, "finalized account deletion");
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
@@ -0,0 +1,506 @@
+//! Export of all data stored for an account, streamed as a single JSON document.
+//!
+//! Sections are read from one repeatable read transaction and sent while their rows are fetched,
+//! the connection is held until the client received everything.
+//!
+//! Secrets (password hashes, key material, TOTP secrets, token hashes) are left out.
+use actix_web::web::Bytes;
+use chrono::Utc;
+use futures::channel::mpsc;
+use futures::{SinkExt, Stream, TryStreamExt};
+use sqlx::{Connection, MySqlConnection, MySqlPool};
+
+use super::*;
+use crate::audit::Activity;
+
+/// Flush threshold for the output buffer
+const CHUNK_SIZE: usize = 16 * 1024;
+/// Chunks buffered before the export waits for the client
+const CHANNEL_SIZE: usize = 4;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportLogin {
+    email: String,
+    verified: bool,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportKey {
+    key_type: String,
+    key_size: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportOidc {
+    provider: String,
+    subject: String,
+    created: Timestamp,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportSession {
+    device: Option<String>,
+    created: Timestamp,
+    last_used: Timestamp,
+    method: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportTotp {
+    confirmed: bool,
+    created: Timestamp,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportList {
+    uuid: Uuid,
+    name: String,
+    name_a: String,
+    name_b: String,
+    created: Timestamp,
+    changed: Timestamp,
+    /// Set for lists in the trash
+    deleted: Option<Timestamp>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportMeaning {
+    value: String,
+    is_a: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportEntry {
+    uuid: Uuid,
+    tip: Option<String>,
+    changed: Timestamp,
+    meanings: Vec<ExportMeaning>,
+}
+
+/// List joined with its entries and their meanings
+#[derive(sqlx::FromRow)]
+struct ListRow {
+    uuid: Uuid,
+    name: String,
+    name_a: String,
+    name_b: String,
+    created: Timestamp,
+    changed: Timestamp,
+    deleted: Option<Timestamp>,
+    entry: Option<Uuid>,
+    tip: Option<String>,
+    entry_changed: Option<Timestamp>,
+    value: Option<String>,
+    is_a: Option<bool>,
+}
+
+/// Revision authored by the user
+#[derive(Debug, Serialize)]
+struct ExportRevision {
+    id: u64,
+    list: Uuid,
+    entry: Option<Uuid>,
+    action: String,
+    created: Timestamp,
+    name: Option<String>,
+    name_a: Option<String>,
+    name_b: Option<String>,
+    tip: Option<String>,
+    meanings: Vec<ExportMeaning>,
+}
+
+/// Revision joined with its meanings
+#[derive(sqlx::FromRow)]
+struct RevisionRow {
+    id: u64,
+    list: Uuid,
+    entry: Option<Uuid>,
+    action: String,
+    created: Timestamp,
+    name: Option<String>,
+    name_a: Option<String>,
+    name_b: Option<String>,
+    tip: Option<String>,
+    value: Option<String>,
+    is_a: Option<bool>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportMembership {
+    list: Uuid,
+    name: String,
+    write: bool,
+    reshare: bool,
+    changed: Timestamp,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportShareToken {
+    list: Uuid,
+    deadline: Timestamp,
+    write: bool,
+    reshare: bool,
+    reusable: bool,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportEmailChange {
+    email: String,
+    created: Timestamp,
+    expires: Timestamp,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportDeletion {
+    requested: Timestamp,
+    delete_at: Timestamp,
+}
+
+/// Stream the export of the user.
+/// Runs in its own task, a failure after the first chunk aborts the response.
+pub fn export_user(pool: MySqlPool, user: UserId) -> impl Stream<Item = Result<Bytes>> {
+    let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+    actix_rt::spawn(async move {
+        let mut writer = JsonWriter::new(tx);
+        if let Err(e) = write_export(&pool, &user, &mut writer).await {
+            warn!(%user, ?e, "account export failed");
+            let _ = writer.tx.send(Err(e)).await;
+        }
+    });
+    rx
+}
+
+/// Write all sections as one JSON object
+async fn write_export(pool: &MySqlPool, user: &UserId, w: &mut JsonWriter) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+    // one snapshot of all tables, taken by the first read
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        .execute(&mut conn)
+        .await?;
+    let mut transaction = conn.begin().await?;
+    let sql: &mut MySqlConnection = &mut transaction;
+
+    let account = dao::user_by_uuid(&mut *sql, user)
+        .await?
+        .ok_or(AuthError::UnknownUser)?;
+    w.push("{\"user\":");
+    w.value(&account)?;
+
+    let sql_logins = "SELECT email,verified FROM user_login WHERE user_id = ?";
+    w.push(",\"logins\":");
+    w.rows(
+        sqlx::query_as::<_, ExportLogin>(sql_logins)
+            .bind(user.0)
+            .fetch(&mut *sql),
+    )
+    .await?;
+
+    let sql_keys = "SELECT t.name AS key_type, LENGTH(k.auth_key) AS key_size
+        FROM user_key k JOIN key_type t ON t.id = k.key_type WHERE k.user_id = ?";
+    w.push(",\"keys\":");
+    w.rows(
+        sqlx::query_as::<_, ExportKey>(sql_keys)
+            .bind(user.0)
+            .fetch(&mut *sql),
+    )
+    .await?;
+
+    let sql_oidc = "SELECT provider,subject,created FROM user_oidc WHERE user_id = ?";
+    w.push(",\"oidc\":");
+    w.rows(
+        sqlx::query_as::<_, ExportOidc>(sql_oidc)
+            .bind(user.0)
+            .fetch(&mut *sql),
+    )
+    .await?;
+
+    let sql_totp = "SELECT confirmed,created FROM user_totp WHERE user_id = ?";
+    let totp: Option<ExportTotp> = sqlx::query_as(sql_totp)
+        .bind(user.0)
+        .fetch_optional(&mut *sql)
+        .await?;
+    w.push(",\"totp\":");
+    w.value(&totp)?;
+
+    let sql_sessions = "SELECT device,created,last_used,method FROM user_session
+        WHERE user_id = ? ORDER BY created";
+    w.push(",\"sessions\":");
+    w.rows(
+        sqlx::query_as::<_, ExportSession>(sql_sessions)
+            .bind(user.0)
+            .fetch(&mut *sql),
+    )
+    .await?;
+
+    let sql_email_change = "SELECT email,created,expires FROM email_change WHERE user_id = ?";
+    let email_change: Option<ExportEmailChange> = sqlx::query_as(sql_email_change)
+        .bind(user.0)
+        .fetch_optional(&mut *sql)
+        .await?;
+    w.push(",\"email_change\":");
+    w.value(&email_change)?;
+
+    let sql_deletion = "SELECT requested,delete_at FROM pending_deletion WHERE user_id = ?";
+    let deletion: Option<ExportDeletion> = sqlx::query_as(sql_deletion)
+        .bind(user.0)
+        .fetch_optional(&mut *sql)
+        .await?;
+    w.push(",\"pending_deletion\":");
+    w.value(&deletion)?;
+
+    w.push(",\"lists\":");
+    write_lists(&mut *sql, user, w).await?;
+
+    let sql_shared = "SELECT p.list,l.name,p.write,p.reshare,p.changed FROM list_permissions p
+        JOIN lists l ON l.uuid = p.list WHERE p.user = ? AND l.deleted IS NULL";
+    w.push(",\"shared_lists\":");
+    w.rows(
+        sqlx::query_as::<_, ExportMembership>(sql_shared)
+            .bind(user.0)
+            .fetch(&mut *sql),
+    )
+    .await?;
+
+    let sql_tokens = "SELECT t.list,t.deadline,t.write,t.reshare,t.reusable FROM share_token t
+        JOIN lists l ON l.uuid = t.list WHERE l.owner = ? AND t.deadline > ?";
+    w.push(",\"share_tokens\":");
+    w.rows(
+        sqlx::query_as::<_, ExportShareToken>(sql_tokens)
+            .bind(user.0)
+            .bind(Utc::now().naive_utc())
+            .fetch(&mut *sql),
+    )
+    .await?;
+
+    w.push(",\"revisions\":");
+    write_revisions(&mut *sql, user, w).await?;
+
+    // events of and caused by the user, as shown in the activity
+    let sql_audit = "SELECT id,user_id,actor,event,list,ip,detail,created FROM audit_log
+        WHERE user_id = ? OR actor = ? ORDER BY id";
+    w.push(",\"audit_log\":");
+    w.rows(
+        sqlx::query_as::<_, Activity>(sql_audit)
+            .bind(user.0)
+            .bind(user.0)
+            .fetch(&mut *sql),
+    )
+    .await?;
+
+    w.push("}");
+    w.flush().await?;
+    transaction.rollback().await?;
+    trace!(%user, "exported account");
+    Ok(())
+}
+
+/// Owned lists with their entries, each list is written as soon as its rows are read
+async fn write_lists(sql: &mut MySqlConnection, user: &UserId, w: &mut JsonWriter) -> Result<()> {
+    let sql_lists = "SELECT l.uuid,l.name,l.name_a,l.name_b,l.created,l.changed,l.deleted,
+        e.uuid AS `entry`,e.tip,e.changed AS entry_changed,m.value,m.is_a
+        FROM lists l
+        LEFT JOIN entries e ON e.list = l.uuid
+        LEFT JOIN entry_meaning m ON m.entry = e.uuid
+        WHERE l.owner = ? ORDER BY l.created,l.uuid,e.uuid";
+    let mut rows = sqlx::query_as::<_, ListRow>(sql_lists)
+        .bind(user.0)
+        .fetch(sql);
+    let mut lists = Separator::default();
+    let mut entries = Separator::default();
+    let mut list: Option<Uuid> = None;
+    let mut entry: Option<ExportEntry> = None;
+    w.push("[");
+    while let Some(row) = rows.try_next().await? {
+        // rows are ordered by list and entry, entries and meanings are adjacent
+        if list != Some(row.uuid) {
+            if let Some(entry) = entry.take() {
+                w.item(&mut entries, &entry)?;
+            }
+            if list.is_some() {
+                w.push("]}");
+            }
+            w.push(lists.next());
+            // same fields as ExportList, entries are appended to the object
+            w.value(&ExportList {
+                uuid: row.uuid,
+                name: row.name,
+                name_a: row.name_a,
+                name_b: row.name_b,
+                created: row.created,
+                changed: row.changed,
+                deleted: row.deleted,
+            })?;
+            w.pop_object_end();
+            w.push(",\"entries\":[");
+            list = Some(row.uuid);
+            entries = Separator::default();
+        }
+        let (uuid, changed) = match (row.entry, row.entry_changed) {
+            (Some(uuid), Some(changed)) => (uuid, changed),
+            _ => continue,
+        };
+        if entry.as_ref().map(|e| e.uuid) != Some(uuid) {
+            let next = ExportEntry {
+                uuid,
+                tip: row.tip,
+                changed,
+                meanings: Vec::new(),
+            };
+            if let Some(done) = entry.replace(next) {
+                w.item(&mut entries, &done)?;
+                w.flush_full().await?;
+            }
+        }
+        if let (Some(value), Some(is_a), Some(entry)) = (row.value, row.is_a, entry.as_mut()) {
+            entry.meanings.push(ExportMeaning { value, is_a });
+        }
+    }
+    if let Some(entry) = entry {
+        w.item(&mut entries, &entry)?;
+    }
+    if list.is_some() {
+        w.push("]}");
+    }
+    w.push("]");
+    w.flush_full().await?;
+    Ok(())
+}
+
+/// Revisions authored by the user with their meanings, oldest first
+async fn write_revisions(
+    sql: &mut MySqlConnection,
+    user: &UserId,
+    w: &mut JsonWriter,
+) -> Result<()> {
+    let sql_revisions = "SELECT r.id,r.list,r.`entry`,r.action,r.created,
+        r.name,r.name_a,r.name_b,r.tip,m.value,m.is_a
+        FROM revision r LEFT JOIN revision_meaning m ON m.revision = r.id
+        WHERE r.user = ? ORDER BY r.id";
+    let mut rows = sqlx::query_as::<_, RevisionRow>(sql_revisions)
+        .bind(user.0)
+        .fetch(sql);
+    let mut separator = Separator::default();
+    let mut revision: Option<ExportRevision> = None;
+    w.push("[");
+    while let Some(row) = rows.try_next().await? {
+        // rows are ordered by revision, meanings of a revision are adjacent
+        if revision.as_ref().map(|r| r.id) != Some(row.id) {
+            let next = ExportRevision {
+                id: row.id,
+                list: row.list,
+                entry: row.entry,
+                action: row.action,
+                created: row.created,
+                name: row.name,
+                name_a: row.name_a,
+                name_b: row.name_b,
+                tip: row.tip,
+                meanings: Vec::new(),
+            };
+            if let Some(done) = revision.replace(next) {
+                w.item(&mut separator, &done)?;
+                w.flush_full().await?;
+            }
+        }
+        if let (Some(value), Some(is_a), Some(revision)) = (row.value, row.is_a, revision.as_mut())
+        {
+            revision.meanings.push(ExportMeaning { value, is_a });
+        }
+    }
+    if let Some(revision) = revision {
+        w.item(&mut separator, &revision)?;
+    }
+    w.push("]");
+    w.flush_full().await?;
+    Ok(())
+}
+
+/// Commas between the items of a JSON array
+#[derive(Default)]
+struct Separator {
+    started: bool,
+}
+
+impl Separator {
+    fn next(&mut self) -> &'static str {
+        match std::mem::replace(&mut self.started, true) {
+            true => ",",
+            false => "",
+        }
+    }
+}
+
+/// Serializes values and sends them in chunks
+struct JsonWriter {
+    tx: mpsc::Sender<Result<Bytes>>,
+    buffer: Vec<u8>,
+}
+
+impl JsonWriter {
+    fn new(tx: mpsc::Sender<Result<Bytes>>) -> Self {
+        Self {
+            tx,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    fn push(&mut self, raw: &str) {
+        self.buffer.extend_from_slice(raw.as_bytes());
+    }
+
+    fn value<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.buffer, value)?;
+        Ok(())
+    }
+
+    /// Array item, preceded by a comma if required
+    fn item<T: Serialize>(&mut self, separator: &mut Separator, value: &T) -> Result<()> {
+        self.push(separator.next());
+        self.value(value)
+    }
+
+    /// Remove the closing brace of the last written object, to append more fields
+    fn pop_object_end(&mut self) {
+        debug_assert_eq!(Some(&b'}'), self.buffer.last());
+        self.buffer.pop();
+    }
+
+    /// All rows of the query as array, sent while they are fetched
+    async fn rows<T, S>(&mut self, mut rows: S) -> Result<()>
+    where
+        T: Serialize,
+        S: Stream<Item = sqlx::Result<T>> + Unpin,
+    {
+        let mut separator = Separator::default();
+        self.push("[");
+        while let Some(row) = rows.try_next().await? {
+            self.item(&mut separator, &row)?;
+            self.flush_full().await?;
+        }
+        self.push("]");
+        Ok(())
+    }
+
+    /// Send the buffer once it reached the chunk size
+    async fn flush_full(&mut self) -> Result<()> {
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(
+            &mut self.buffer,
+            Vec::with_capacity(CHUNK_SIZE),
+        ));
+        self.tx
+            .send(Ok(chunk))
+            .await
+            .context("export receiver dropped")?;
+        Ok(())
+    }
+}
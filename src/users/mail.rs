@@ -0,0 +1,80 @@
+//! Delivery of account mails, replaceable for tests.
+//!
+//! Tokens in mails are credentials and must never be logged.
+use futures::future::BoxFuture;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use super::{AuthError, Result};
+use crate::config;
+use crate::prelude::*;
+
+/// Mail delivery backend
+pub trait MailSender: Send + Sync {
+    /// Send the confirmation token of an email change to the new address
+    fn email_change<'a>(&'a self, to: &'a str, token: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Used while no mail server is configured, features requiring mails are unavailable
+pub struct NoMailSender;
+
+impl MailSender for NoMailSender {
+    fn email_change<'a>(&'a self, _to: &'a str, _token: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Err(AuthError::MailUnavailable) })
+    }
+}
+
+/// Delivery via the configured SMTP server
+pub struct SmtpMailSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailSender {
+    pub fn new(settings: &config::Mail) -> color_eyre::Result<Self> {
+        let from: Mailbox = settings.from.parse().context("invalid mail.from address")?;
+        let mut builder = match settings.tls {
+            true => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host),
+            false => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host),
+        }
+        .context("invalid mail.host")?;
+        if let Some(port) = settings.port {
+            builder = builder.port(port);
+        }
+        if let Some(user) = settings.user.as_ref() {
+            builder = builder.credentials(Credentials::new(
+                user.clone(),
+                settings.password.clone().unwrap_or_default(),
+            ));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+impl MailSender for SmtpMailSender {
+    fn email_change<'a>(&'a self, to: &'a str, token: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let to: Mailbox = to.parse().map_err(|_| AuthError::InvalidInput("email"))?;
+            let message = Message::builder()
+                .from(self.from.clone())
+                .to(to)
+                .subject("Confirm your new email address")
+                .header(ContentType::TEXT_PLAIN)
+                .body(format!(
+                    "Enter this code to confirm the change of your email address:\n\n{}\n\n\
+                    Ignore this mail if you didn't request the change.\n",
+                    token
+                ))
+                .context("building mail")?;
+            if let Err(e) = self.transport.send(message).await {
+                warn!(?e, "sending mail failed");
+                return Err(AuthError::MailUnavailable);
+            }
+            Ok(())
+        })
+    }
+}
@@ -1,11 +1,19 @@
 use crate::prelude::*;
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{http::header, HttpResponse, ResponseError};
 use thiserror::Error;
 
+pub mod auth;
 pub mod dao;
+pub mod deletion;
+pub mod export;
+pub mod mail;
+pub mod oidc;
+pub mod ratelimit;
 pub mod routes;
+pub mod totp;
 pub mod user;
 
+pub use auth::AuthUser;
 pub use dao::update_last_seen;
 
 #[cfg(test)]
@@ -39,6 +47,30 @@ pub enum AuthError {
     UnknownUser,
     #[error("user deleted")]
     DeletedUser,
+    #[error("invalid proof: {0}")]
+    InvalidProof(&'static str),
+    #[error("proof already used")]
+    ReplayedProof,
+    #[error("session unknown")]
+    UnknownSession,
+    #[error("too many login attempts, retry after {0}s")]
+    RateLimited(u64),
+    #[error("2FA already enabled")]
+    TotpEnabled,
+    #[error("2FA not enrolled")]
+    TotpNotEnrolled,
+    #[error("no password login")]
+    NoPasswordLogin,
+    #[error("unknown OIDC provider")]
+    UnknownOidcProvider,
+    #[error("OIDC provider error: {0}")]
+    OidcProvider(&'static str),
+    #[error("http client error")]
+    Http(#[from] reqwest::Error),
+    #[error("invalid input: {0}")]
+    InvalidInput(&'static str),
+    #[error("mail delivery unavailable")]
+    MailUnavailable,
 }
 
 fn jwt_err_into_response(error: &jsonwebtoken::errors::Error) -> HttpResponse {
@@ -49,13 +81,15 @@ fn jwt_err_into_response(error: &jsonwebtoken::errors::Error) -> HttpResponse {
             InvalidToken => "invalid JWT token",
             InvalidSignature => "invalid JWT signature",
             InvalidEcdsaKey => "ecdsa key invalid",
-            InvalidRsaKey => "rsa key invalid",
+            InvalidRsaKey(_) => "rsa key invalid",
             ExpiredSignature => "rsa key invalid",
             InvalidAlgorithmName => "rsa key invalid",
             InvalidKeyFormat => "invalid key format",
             InvalidIssuer => "iss invalid",
             InvalidAlgorithm => "key/decode algorithm mismatch",
             InvalidAudience => "aud invalid",
+            InvalidSubject => "sub invalid",
+            MissingRequiredClaim(_) => "missing required claim",
             _ => "JWT invalid",
         })
 }
@@ -84,6 +118,40 @@ impl ResponseError for AuthError {
             AuthError::UnknownUser => HttpResponse::BadRequest()
                 .reason("account unknown")
                 .finish(),
+            AuthError::InvalidProof(reason) => HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(*reason),
+            AuthError::ReplayedProof => HttpResponse::Forbidden()
+                .reason("proof already used")
+                .finish(),
+            AuthError::UnknownSession => {
+                HttpResponse::NotFound().reason("session unknown").finish()
+            }
+            AuthError::RateLimited(secs) => HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, secs.to_string()))
+                .finish(),
+            AuthError::TotpEnabled => HttpResponse::Conflict()
+                .reason("2FA already enabled")
+                .finish(),
+            AuthError::TotpNotEnrolled => HttpResponse::BadRequest()
+                .reason("2FA not enrolled")
+                .finish(),
+            AuthError::NoPasswordLogin => HttpResponse::BadRequest()
+                .reason("no password login")
+                .finish(),
+            AuthError::UnknownOidcProvider => HttpResponse::NotFound()
+                .reason("unknown OIDC provider")
+                .finish(),
+            AuthError::InvalidInput(reason) => HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(*reason),
+            AuthError::MailUnavailable => HttpResponse::ServiceUnavailable()
+                .reason("mail delivery unavailable")
+                .finish(),
+            AuthError::OidcProvider(_) | AuthError::Http(_) => {
+                warn!("{}", self);
+                HttpResponse::BadGateway().finish()
+            }
             e => {
                 warn!("{}", e);
                 HttpResponse::InternalServerError().finish()
@@ -0,0 +1,315 @@
+//! OpenID Connect authorization code flow with PKCE
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use actix_web::cookie::{Cookie, CookieJar, Key, SameSite};
+use base64ct::{Base64UrlUnpadded, Encoding};
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use rand_core::{OsRng, RngCore};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use super::*;
+use crate::config::OidcProvider;
+
+/// Lifetime of started login flows in minutes
+pub const FLOW_LIFETIME: i64 = 10;
+/// Refetch discovery + keys after this time
+const METADATA_LIFETIME: Duration = Duration::from_secs(60 * 60);
+/// Minimum time between refetches caused by unknown key ids
+const KEY_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// Signed cookie binding a started flow to the browser
+pub const STATE_COOKIE: &str = "oidc_state";
+/// Allowed clock skew for ID tokens in seconds
+const ID_TOKEN_LEEWAY: u64 = 30;
+
+/// Subset of the provider metadata we use
+#[derive(Debug, Deserialize)]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+struct Metadata {
+    discovery: Discovery,
+    jwks: JwkSet,
+    fetched: Instant,
+}
+
+/// Validated ID token claims
+#[derive(Debug, Deserialize)]
+pub struct IdClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub name: Option<String>,
+    pub preferred_username: Option<String>,
+}
+
+impl IdClaims {
+    /// Display name for new accounts
+    pub fn display_name(&self) -> &str {
+        self.name
+            .as_deref()
+            .or(self.preferred_username.as_deref())
+            .unwrap_or("")
+    }
+}
+
+/// Started login, redirect the user to `url`
+pub struct AuthRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub verifier: String,
+}
+
+#[derive(Deserialize)]
+struct TokenEndpointResponse {
+    id_token: String,
+}
+
+/// Client for all configured providers, caching their metadata
+pub struct OidcClient {
+    http: reqwest::Client,
+    providers: HashMap<String, OidcProvider>,
+    metadata: RwLock<HashMap<String, Arc<Metadata>>>,
+}
+
+impl OidcClient {
+    pub fn new(providers: HashMap<String, OidcProvider>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            providers,
+            metadata: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn provider(&self, name: &str) -> Result<&OidcProvider> {
+        self.providers
+            .get(name)
+            .ok_or(AuthError::UnknownOidcProvider)
+    }
+
+    /// Fetch discovery document of issuer
+    pub async fn discover(&self, issuer: &str) -> Result<Discovery> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let discovery: Discovery = self
+            .http
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // prevents mix-up with other issuers
+        if discovery.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+            return Err(AuthError::OidcProvider("discovery issuer mismatch"));
+        }
+        Ok(discovery)
+    }
+
+    /// Cached metadata, `refresh` refetches unless fetched within [KEY_REFRESH_INTERVAL]
+    async fn metadata(&self, name: &str, refresh: bool) -> Result<Arc<Metadata>> {
+        if let Some(v) = self.metadata.read().unwrap().get(name) {
+            let max_age = match refresh {
+                true => KEY_REFRESH_INTERVAL,
+                false => METADATA_LIFETIME,
+            };
+            if v.fetched.elapsed() < max_age {
+                return Ok(v.clone());
+            }
+        }
+        let provider = self.provider(name)?;
+        let discovery = self.discover(&provider.issuer).await?;
+        let jwks: JwkSet = self
+            .http
+            .get(&discovery.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        trace!(
+            provider = name,
+            keys = jwks.keys.len(),
+            "fetched oidc metadata"
+        );
+        let metadata = Arc::new(Metadata {
+            discovery,
+            jwks,
+            fetched: Instant::now(),
+        });
+        self.metadata
+            .write()
+            .unwrap()
+            .insert(name.to_owned(), metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Start login at provider
+    pub async fn authorization_request(&self, name: &str) -> Result<AuthRequest> {
+        let provider = self.provider(name)?;
+        let metadata = self.metadata(name, false).await?;
+        let state = random_token();
+        let nonce = random_token();
+        let verifier = random_token();
+        let url = Url::parse_with_params(
+            &metadata.discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &provider.client_id),
+                ("redirect_uri", &provider.redirect_uri),
+                ("scope", &provider.scopes.join(" ")),
+                ("state", &state),
+                ("nonce", &nonce),
+                ("code_challenge", &pkce_challenge(&verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|_| AuthError::OidcProvider("invalid authorization endpoint"))?;
+        Ok(AuthRequest {
+            url: url.to_string(),
+            state,
+            nonce,
+            verifier,
+        })
+    }
+
+    /// Exchange authorization code, returns the raw ID token
+    pub async fn exchange_code(&self, name: &str, code: &str, verifier: &str) -> Result<String> {
+        let provider = self.provider(name)?;
+        let metadata = self.metadata(name, false).await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &provider.redirect_uri),
+            ("client_id", &provider.client_id),
+            ("code_verifier", verifier),
+        ];
+        if let Some(secret) = &provider.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let res = self
+            .http
+            .post(&metadata.discovery.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            debug!(status = %res.status(), "oidc code exchange failed");
+            return Err(AuthError::InvalidCredentials);
+        }
+        let res: TokenEndpointResponse = res.json().await?;
+        Ok(res.id_token)
+    }
+
+    /// Validate signature, issuer, audience, expiry and nonce of an ID token
+    pub async fn validate_id_token(
+        &self,
+        name: &str,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<IdClaims> {
+        let provider = self.provider(name)?;
+        let header = decode_header(id_token).map_err(|e| {
+            debug!(?e, "invalid id token header");
+            AuthError::InvalidCredentials
+        })?;
+        // no symmetric algorithms, the key set is public
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(AuthError::InvalidCredentials);
+        }
+        let kid = header.kid.as_deref().unwrap_or_default();
+        let mut metadata = self.metadata(name, false).await?;
+        if find_key(&metadata.jwks, kid).is_none() {
+            // key rotation, throttled as the key id is attacker controlled
+            metadata = self.metadata(name, true).await?;
+        }
+        let jwk = find_key(&metadata.jwks, kid).ok_or(AuthError::InvalidCredentials)?;
+        if matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)) {
+            return Err(AuthError::InvalidCredentials);
+        }
+        let key = DecodingKey::from_jwk(jwk)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.discovery.issuer]);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.leeway = ID_TOKEN_LEEWAY;
+        let td = decode::<IdClaims>(id_token, &key, &validation).map_err(|e| {
+            debug!(?e, "invalid id token");
+            AuthError::InvalidCredentials
+        })?;
+        if td.claims.nonce.as_deref() != Some(nonce) {
+            debug!("id token nonce mismatch");
+            return Err(AuthError::InvalidCredentials);
+        }
+        Ok(td.claims)
+    }
+}
+
+/// Key by id, or the only key if the token has none
+fn find_key<'a>(jwks: &'a JwkSet, kid: &str) -> Option<&'a jsonwebtoken::jwk::Jwk> {
+    match kid {
+        "" if jwks.keys.len() == 1 => jwks.keys.first(),
+        "" => None,
+        kid => jwks.find(kid),
+    }
+}
+
+/// S256 PKCE challenge of the verifier
+pub fn pkce_challenge(verifier: &str) -> String {
+    Base64UrlUnpadded::encode_string(&Sha256::digest(verifier.as_bytes()))
+}
+
+/// Random URL safe token for state, nonce and PKCE verifier
+fn random_token() -> String {
+    let mut token = [0u8; 32];
+    OsRng.fill_bytes(&mut token);
+    Base64UrlUnpadded::encode_string(&token)
+}
+
+/// Hash of the state parameter, flows are stored by it
+pub fn state_hash(state: &str) -> Vec<u8> {
+    Sha256::digest(state.as_bytes()).to_vec()
+}
+
+/// HttpOnly cookie with the signed state, only sent to the OIDC routes
+pub fn state_cookie(key: &Key, state: &str, secure: bool) -> Cookie<'static> {
+    let cookie = Cookie::build(STATE_COOKIE, state.to_owned())
+        .path("/api/v1/account/oidc")
+        .http_only(true)
+        .secure(secure)
+        // sent on the top level redirect back from the provider
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::minutes(FLOW_LIFETIME))
+        .finish();
+    let mut jar = CookieJar::new();
+    jar.signed_mut(key).add(cookie);
+    jar.get(STATE_COOKIE).expect("missing added cookie").clone()
+}
+
+/// Check the state cookie of the callback request against the returned state
+pub fn verify_state_cookie(key: &Key, cookie: Option<Cookie<'static>>, state: &str) -> bool {
+    let cookie = match cookie {
+        Some(v) => v,
+        None => return false,
+    };
+    let mut jar = CookieJar::new();
+    jar.add_original(cookie);
+    match jar.signed(key).get(STATE_COOKIE) {
+        Some(v) => v.value().as_bytes().ct_eq(state.as_bytes()).into(),
+        None => false,
+    }
+}
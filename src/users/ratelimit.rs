@@ -0,0 +1,267 @@
+//! Throttling of failed password logins, per client IP and per account.
+//!
+//! Each failed attempt over the free allowance blocks further attempts of the same key,
+//! doubling the delay on every additional failure up to a maximum.
+//! Attempts are reserved while being verified, so parallel requests can't exceed the allowance.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::LoginLimit;
+
+/// Time source, replaceable for tests
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// Clock using [Instant::now]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Failed attempts of a single key
+#[derive(Debug, Clone, Copy)]
+pub struct Attempts {
+    pub failures: u32,
+    pub last_failure: Instant,
+    pub blocked_until: Option<Instant>,
+}
+
+/// Storage backend for attempt counters
+pub trait LimitStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<Attempts>;
+    fn put(&self, key: &str, attempts: Attempts);
+    fn remove(&self, key: &str);
+    /// Remove all entries that aren't blocked and have no failure since `before`
+    fn prune(&self, before: Instant);
+}
+
+/// In-memory store, not shared between server instances
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, Attempts>>,
+}
+
+impl LimitStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<Attempts> {
+        self.entries.lock().unwrap().get(key).copied()
+    }
+
+    fn put(&self, key: &str, attempts: Attempts) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_owned(), attempts);
+    }
+
+    fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    fn prune(&self, before: Instant) {
+        self.entries.lock().unwrap().retain(|_, v| {
+            v.last_failure >= before || v.blocked_until.map_or(false, |t| t >= before)
+        });
+    }
+}
+
+/// Password login limiter
+pub struct LoginLimiter {
+    config: LoginLimit,
+    store: Box<dyn LimitStore>,
+    clock: Box<dyn Clock>,
+    last_prune: Mutex<Instant>,
+    /// Attempts currently being verified per key, also serializes checks
+    in_flight: Mutex<HashMap<String, u32>>,
+}
+
+impl LoginLimiter {
+    /// Limiter with in-memory store and system clock
+    pub fn new(config: LoginLimit) -> Self {
+        Self::with_parts(
+            config,
+            Box::new(MemoryStore::default()),
+            Box::new(SystemClock),
+        )
+    }
+
+    pub fn with_parts(
+        config: LoginLimit,
+        store: Box<dyn LimitStore>,
+        clock: Box<dyn Clock>,
+    ) -> Self {
+        let now = clock.now();
+        Self {
+            config,
+            store,
+            clock,
+            last_prune: Mutex::new(now),
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check whether a login attempt is allowed and reserve it, returns the time to wait otherwise.
+    /// The account is identified by its email, or its user id for second factor logins.
+    /// In-flight attempts count as failures, over the free allowance only one is allowed at a time.
+    pub fn check(&self, ip: Option<&str>, account: &str) -> Result<LoginAttempt<'_>, Duration> {
+        let keys = match self.config.enabled {
+            true => self.keys(ip, account),
+            false => Vec::new(),
+        };
+        let now = self.clock.now();
+        let reset_after = Duration::from_secs(self.config.reset_after);
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let mut retry_after = None;
+        for (key, free_attempts) in keys.iter() {
+            let attempts = self.store.get(key);
+            let pending = in_flight.get(key).copied().unwrap_or(0);
+            let failures = match attempts {
+                Some(v) if now - v.last_failure <= reset_after => v.failures,
+                _ => 0,
+            };
+            let wait = match attempts.and_then(|v| v.blocked_until) {
+                Some(until) if until > now => until - now,
+                // delay if all in-flight attempts fail
+                _ if pending > 0 => match self.backoff(failures + pending, *free_attempts) {
+                    Some(v) => v,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            retry_after = retry_after.max(Some(wait));
+        }
+        if let Some(v) = retry_after {
+            return Err(v);
+        }
+        for (key, _) in keys.iter() {
+            *in_flight.entry(key.clone()).or_default() += 1;
+        }
+        Ok(LoginAttempt {
+            limiter: self,
+            ip: ip.map(str::to_owned),
+            account: account.to_owned(),
+            keys: keys.into_iter().map(|(key, _)| key).collect(),
+        })
+    }
+
+    /// Record a failed login attempt
+    pub fn failure(&self, ip: Option<&str>, account: &str) {
+        if !self.config.enabled {
+            return;
+        }
+        let now = self.clock.now();
+        let reset_after = Duration::from_secs(self.config.reset_after);
+        self.prune(now, reset_after);
+        for (key, free_attempts) in self.keys(ip, account) {
+            let failures = match self.store.get(&key) {
+                // forget old failures
+                Some(v) if now - v.last_failure <= reset_after => v.failures + 1,
+                _ => 1,
+            };
+            let blocked_until = self
+                .backoff(failures, free_attempts)
+                .map(|delay| now + delay);
+            self.store.put(
+                &key,
+                Attempts {
+                    failures,
+                    last_failure: now,
+                    blocked_until,
+                },
+            );
+        }
+    }
+
+    /// Record a successful login, resets the counter of the account.
+    /// IP counters are kept, an attacker could otherwise reset them with an own account.
+    pub fn success(&self, account: &str) {
+        self.store.remove(&account_key(account));
+    }
+
+    /// Delay after the given amount of failures, None while inside the free allowance
+    fn backoff(&self, failures: u32, free_attempts: u32) -> Option<Duration> {
+        if failures <= free_attempts {
+            return None;
+        }
+        let exponent = (failures - free_attempts - 1).min(31);
+        let delay = self
+            .config
+            .base_delay
+            .saturating_mul(1u64 << exponent)
+            .min(self.config.max_delay);
+        Some(Duration::from_secs(delay))
+    }
+
+    fn keys(&self, ip: Option<&str>, account: &str) -> Vec<(String, u32)> {
+        let mut keys = vec![(account_key(account), self.config.free_attempts)];
+        if let Some(ip) = ip {
+            keys.push((format!("ip:{}", ip), self.config.free_attempts_ip));
+        }
+        keys
+    }
+
+    /// Remove the reservation of an attempt, `in_flight` must be locked
+    fn release(&self, in_flight: &mut HashMap<String, u32>, keys: &[String]) {
+        for key in keys {
+            if let Some(pending) = in_flight.get_mut(key) {
+                *pending -= 1;
+                if *pending == 0 {
+                    in_flight.remove(key);
+                }
+            }
+        }
+    }
+
+    fn prune(&self, now: Instant, reset_after: Duration) {
+        let mut last_prune = self.last_prune.lock().unwrap();
+        if now - *last_prune > reset_after {
+            if let Some(before) = now.checked_sub(reset_after) {
+                self.store.prune(before);
+            }
+            *last_prune = now;
+        }
+    }
+}
+
+/// Reserved login attempt, released without a result when dropped
+pub struct LoginAttempt<'a> {
+    limiter: &'a LoginLimiter,
+    ip: Option<String>,
+    account: String,
+    keys: Vec<String>,
+}
+
+impl LoginAttempt<'_> {
+    /// Record the attempt as failed
+    pub fn failure(mut self) {
+        let mut in_flight = self.limiter.in_flight.lock().unwrap();
+        self.limiter.failure(self.ip.as_deref(), &self.account);
+        self.limiter.release(&mut in_flight, &self.keys);
+        self.keys.clear();
+    }
+
+    /// Record the attempt as successful
+    pub fn success(mut self) {
+        let mut in_flight = self.limiter.in_flight.lock().unwrap();
+        self.limiter.success(&self.account);
+        self.limiter.release(&mut in_flight, &self.keys);
+        self.keys.clear();
+    }
+}
+
+impl Drop for LoginAttempt<'_> {
+    fn drop(&mut self) {
+        if !self.keys.is_empty() {
+            let mut in_flight = self.limiter.in_flight.lock().unwrap();
+            self.limiter.release(&mut in_flight, &self.keys);
+        }
+    }
+}
+
+fn account_key(account: &str) -> String {
+    format!("acc:{}", account.to_lowercase())
+}
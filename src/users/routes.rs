@@ -1,8 +1,10 @@
 use actix_identity::Identity;
 use actix_rt::task;
+use actix_web::http::header;
 use actix_web::HttpRequest;
-use actix_web::{get, post, web, HttpResponse};
+use actix_web::{delete, get, patch, post, web, HttpResponse};
 use argon2::{self, PasswordHasher, PasswordVerifier};
+use chrono::{Duration, Utc};
 use jsonwebtoken::decode;
 use jsonwebtoken::Algorithm;
 use jsonwebtoken::DecodingKey;
@@ -10,85 +12,171 @@ use jsonwebtoken::TokenData;
 use jsonwebtoken::Validation;
 use rand_core::OsRng;
 use serde::de::DeserializeOwned;
-use std::borrow::Borrow;
-use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::future::Future;
 
+use super::mail::MailSender;
+use super::ratelimit::LoginLimiter;
 use super::user::*;
 use super::*;
+use crate::audit::{self, AuditLog, Event};
+use crate::config::{KeyAlgorithms, PasswordHashing};
+use sqlx::{Connection, MySql, Transaction};
 
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(app_register)
         .service(account_info)
+        .service(account_update)
+        .service(account_export)
+        .service(account_activity)
         .service(app_login)
         .service(app_password_register)
         .service(form_login)
+        .service(totp_login)
+        .service(totp_enroll)
+        .service(totp_confirm)
+        .service(totp_disable)
+        .service(token_refresh)
+        .service(oidc_login)
+        .service(oidc_callback)
+        .service(change_password)
+        .service(change_email)
+        .service(confirm_email)
+        .service(account_sessions)
+        .service(session_revoke)
+        .service(sessions_revoke_others)
+        .service(logout)
         .service(account_delete);
 }
 
 /// App user register route.
 #[instrument]
 #[post("/api/v1/account/register/new")]
-async fn app_register(reg: web::Json<AccRegister>, state: AppState) -> Result<HttpResponse> {
+async fn app_register(
+    reg: web::Json<AccRegister>,
+    state: AppState,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
     trace!("acc register request");
     let reg = reg.into_inner();
 
     let server_id = state.id.to_string();
+    let algorithms = state.config.key_algorithms.for_key(&reg.keytype).to_vec();
     // FIX ME: loosing context here for tracing span
     let (reg_claims, auth_key, keytype) = task::spawn_blocking(move || -> Result<_> {
-        let td: TokenData<RegisterClaims> = verify_claims_auth(
+        let td: TokenData<ProofClaims<RegisterClaims>> = verify_claims_auth(
             "register",
             server_id,
             &reg.proof,
             reg.key.as_bytes(),
             &reg.keytype,
+            algorithms,
         )?;
         Ok((td.claims, reg.key, reg.keytype))
     })
     .await
     .context("failed joining verifier thread")??;
 
+    let mut conn = state.sql.acquire().await?;
     let uid = dao::register_user(
-        &mut *state.sql.begin().await?,
-        &reg_claims,
+        &mut conn,
+        &reg_claims.claims,
         auth_key.as_bytes(),
         keytype,
+        Some((&reg_claims.jti, proof_nonce_expiry(reg_claims.exp))),
     )
     .await?;
     trace!(?uid, "registered account with key");
+    AuditLog::new(&UserId(uid), Event::KeyRegistered)
+        .ip(peer_ip(&req).as_deref())
+        .write(&mut conn)
+        .await?;
     Ok(HttpResponse::Ok().finish())
 }
 
-fn verify_claims_auth<T: DeserializeOwned>(
+/// Maximum lifetime (exp - iat) of key signed proofs in seconds
+const MAX_PROOF_LIFETIME: i64 = 60;
+/// Allowed clock skew for key signed proofs in seconds
+const PROOF_LEEWAY: i64 = 5;
+/// Maximum length of a proof nonce, see proof_nonce table
+const MAX_JTI_LENGTH: usize = 64;
+
+/// Verify key signed proof. Caller has to consume the nonce via [dao::use_proof_nonce].
+pub(super) fn verify_claims_auth<T: DeserializeOwned>(
     sub: &str,
     server_id: String,
     input: &str,
     key: &[u8],
     k_type: &KeyType,
-) -> Result<TokenData<T>> {
+    algorithms: Vec<Algorithm>,
+) -> Result<TokenData<ProofClaims<T>>> {
     debug!("verifying with {:?}", k_type);
-    let aud = HashSet::from([server_id]);
-    let algo_ec = vec![Algorithm::ES256, Algorithm::ES384];
-    let mut validation = Validation {
-        aud: Some(aud),
-        // FIXME: allow zero copy in validator
-        sub: Some(sub.to_owned()),
-        leeway: 5,
-        algorithms: algo_ec,
-        ..Validation::default()
-    };
+    let mut validation = Validation::default();
+    validation.set_audience(&[server_id]);
+    validation.set_required_spec_claims(&["exp", "sub", "aud"]);
+    // FIXME: allow zero copy in validator
+    validation.sub = Some(sub.to_owned());
+    validation.leeway = PROOF_LEEWAY as u64;
+    // key type families can't be mixed, see KeyAlgorithms
+    validation.algorithms = algorithms;
     let key = match k_type {
         KeyType::EC_PEM => DecodingKey::from_ec_pem(key)?,
-        KeyType::RSA_PEM => {
-            // can't mix RS and EC untill fixes
-            // https://github.com/Keats/jsonwebtoken/issues/219
-            validation.algorithms = vec![Algorithm::RS256, Algorithm::RS384, Algorithm::RS512];
-            DecodingKey::from_rsa_pem(key)?
-        }
+        KeyType::RSA_PEM => DecodingKey::from_rsa_pem(key)?,
+        KeyType::ED_PEM => DecodingKey::from_ed_pem(key)?,
     };
-    let td = decode::<T>(input, &key, &validation)?;
+    let td = decode::<ProofClaims<T>>(input, &key, &validation)?;
+
+    let claims = &td.claims;
+    if claims.iat > Utc::now().timestamp() + PROOF_LEEWAY {
+        return Err(AuthError::InvalidProof("iat in future"));
+    }
+    if claims.exp <= claims.iat || claims.exp - claims.iat > MAX_PROOF_LIFETIME {
+        return Err(AuthError::InvalidProof("invalid proof lifetime"));
+    }
+    if claims.jti.is_empty() || claims.jti.len() > MAX_JTI_LENGTH {
+        return Err(AuthError::InvalidProof("invalid jti"));
+    }
     Ok(td)
 }
 
+/// Expiration of proof nonces, after which a replay is rejected by the expiry check
+fn proof_nonce_expiry(exp: i64) -> Timestamp {
+    Timestamp::from_timestamp(exp + PROOF_LEEWAY, 0)
+}
+
+/// Verify key signed proof of user for `sub`, consuming its nonce
+async fn verify_key_proof(
+    sql: &mut DbConn,
+    server_id: &Uuid,
+    key_algorithms: &KeyAlgorithms,
+    user: &UserId,
+    key_data: UserKeyParsed,
+    sub: &'static str,
+    proof: String,
+) -> Result<()> {
+    let server_id = server_id.to_string();
+    let algorithms = key_algorithms.for_key(&key_data.key_type).to_vec();
+    let claims = task::spawn_blocking(move || -> Result<_> {
+        let td: TokenData<ProofClaims<LoginClaims>> = verify_claims_auth(
+            sub,
+            server_id,
+            &proof,
+            &key_data.auth_key,
+            &key_data.key_type,
+            algorithms,
+        )?;
+        Ok(td.claims)
+    })
+    .await
+    .context("failed joining verifier thread")??;
+    if claims.claims.iss != user.0 {
+        debug!(%claims.claims.iss,%user,"claim iss != user");
+        return Err(AuthError::InvalidCredentials);
+    }
+    dao::use_proof_nonce(sql, user, &claims.jti, proof_nonce_expiry(claims.exp)).await?;
+    Ok(())
+}
+
 /// App user login
 #[instrument(skip(id))]
 #[post("/api/v1/account/login/key")]
@@ -96,9 +184,12 @@ async fn app_login(
     id: Identity,
     reg: web::Json<AccLoginKey>,
     state: AppState,
+    req: HttpRequest,
 ) -> Result<HttpResponse> {
     trace!("acc login via key");
     let reg = reg.into_inner();
+    let token_login = reg.token;
+    let device = reg.device.clone();
     let mut conn = state.sql.acquire().await?;
     let user = UserId(reg.iss);
     let key_data = match dao::user_key(&mut conn, &user).await? {
@@ -111,42 +202,114 @@ async fn app_login(
         Some(k) => k,
     };
     trace!(?key_data, "user key");
-    let server_id = state.id.to_string();
-    let claims = task::spawn_blocking(move || -> Result<_> {
-        let td: TokenData<LoginClaims> = verify_claims_auth(
-            "login",
-            server_id,
-            &reg.proof,
-            &key_data.auth_key,
-            &key_data.key_type,
-        )?;
-        Ok(td.claims)
-    })
-    .await
-    .context("failed joining verifier thread")??;
-    if claims.iss != user.0 {
-        debug!(%claims.iss,%user,"claim iss != user");
-        return Err(AuthError::InvalidCredentials);
-    }
+    let ip = peer_ip(&req);
+    let res = verify_key_proof(
+        &mut conn,
+        &state.id,
+        &state.config.key_algorithms,
+        &user,
+        key_data,
+        "login",
+        reg.proof,
+    )
+    .await;
+    audit_login(
+        &mut conn,
+        &user,
+        ip.as_deref(),
+        LoginMethod::Key,
+        res.is_ok(),
+    )
+    .await?;
+    res?;
     // TODO: update last seen
-    id.remember(user.to_string());
+    let session =
+        dao::create_session(&mut conn, &user, device.as_deref(), LoginMethod::Key).await?;
+    if token_login {
+        let tokens = issue_tokens(&mut conn, &state, &user, &session).await?;
+        return Ok(HttpResponse::Ok().json(tokens));
+    }
+    id.remember(session.to_string());
     Ok(HttpResponse::Ok().finish())
 }
 
-#[instrument(skip(id))]
+/// Exchange refresh token for a new access + refresh token
+#[instrument(skip(reg, state))]
+#[post("/api/v1/account/token/refresh")]
+async fn token_refresh(reg: web::Json<TokenRefresh>, state: AppState) -> Result<HttpResponse> {
+    let reg = reg.into_inner();
+    let hash = auth::token_hash(&reg.refresh_token)?;
+    let mut conn = state.sql.acquire().await?;
+    let (user, session) = dao::use_refresh_token(&mut conn, &hash)
+        .await?
+        .ok_or(AuthError::InvalidCredentials)?;
+    if dao::session_user(&mut conn, &session).await?.is_none() {
+        return Err(AuthError::InvalidCredentials);
+    }
+    let tokens = issue_tokens(&mut conn, &state, &user, &session).await?;
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// Create new access + refresh token pair for a user session
+async fn issue_tokens(
+    sql: &mut DbConn,
+    state: &AppState,
+    user: &UserId,
+    session: &Uuid,
+) -> Result<auth::TokenResponse> {
+    let access_token = auth::issue_access_token(&state.token_keys, &state.id, user, session)?;
+    let (refresh_token, hash) = auth::generate_token();
+    dao::create_refresh_token(sql, user, session, &hash, auth::refresh_token_expiry()).await?;
+    Ok(auth::TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: auth::ACCESS_TOKEN_LIFETIME,
+        refresh_token,
+    })
+}
+
+/// Delete account, requires a fresh proof of the user
+#[instrument(skip(id, reg, state, req))]
 #[post("/api/v1/account/delete")]
 async fn account_delete(
     id: Identity,
-    reg: web::Json<AccLoginKey>,
+    user: AuthUser,
+    reg: web::Json<AccountDelete>,
     state: AppState,
+    req: HttpRequest,
 ) -> Result<HttpResponse> {
-    let user_id = get_user(&id)?;
-    trace!(?user_id, "account delete request");
+    trace!(user_id = ?user.0, "account delete request");
+    let mut conn = state.sql.acquire().await?;
+    let ip = peer_ip(&req);
+    verify_deletion(
+        &mut conn,
+        &state.login_limiter,
+        &state.id,
+        &state.config.key_algorithms,
+        &user,
+        reg.into_inner(),
+        ip.as_deref(),
+    )
+    .await?;
+    let user_id = user.0;
 
-    dao::delete_user(&mut *state.sql.begin().await?, &user_id).await?;
+    let grace_period = state.config.account_deletion.grace_period;
+    if grace_period == 0 {
+        // sessions and refresh tokens are removed along with the user
+        dao::delete_user(&mut conn, &user_id).await?;
+        id.forget();
+        return Ok(HttpResponse::Ok().finish());
+    }
+    // locked by ending all sessions, finalized by the deletion job
+    let delete_at =
+        dao::schedule_deletion(&mut conn, &user_id, Duration::seconds(grace_period as i64)).await?;
+    AuditLog::new(&user_id, Event::DeletionRequested)
+        .ip(ip.as_deref())
+        .write(&mut conn)
+        .await?;
     id.forget();
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(HttpResponse::Ok().json(DeletionScheduled { delete_at }))
 }
 
 #[instrument(skip(id))]
@@ -157,75 +320,722 @@ async fn form_login(
     state: AppState,
     req: HttpRequest,
 ) -> Result<HttpResponse> {
-    trace!("acc login via key");
+    trace!("acc login via password");
     let reg: AccLoginPassword = reg.into_inner();
 
+    let ip = peer_ip(&req);
+    let mut conn = state.sql.acquire().await?;
+    let user = limited(
+        &state.login_limiter,
+        ip.as_deref(),
+        &reg.email,
+        verify_password_login(
+            &mut conn,
+            &reg.email,
+            reg.password,
+            &state.config.password_hashing,
+            ip.as_deref(),
+        ),
+    )
+    .await?;
+    if let Some(user_totp) = dao::totp(&mut conn, &user).await? {
+        if user_totp.confirmed {
+            trace!(%user, "second factor required");
+            let challenge =
+                auth::issue_totp_challenge(&state.token_keys, &state.id, &user, reg.device)?;
+            return Ok(HttpResponse::Ok().json(TotpChallenge {
+                totp_required: true,
+                challenge,
+                expires_in: auth::TOTP_CHALLENGE_LIFETIME,
+            }));
+        }
+    }
+    // TODO: update last seen
+    let session = dao::create_session(
+        &mut conn,
+        &user,
+        reg.device.as_deref(),
+        LoginMethod::Password,
+    )
+    .await?;
+    audit_login(&mut conn, &user, ip.as_deref(), LoginMethod::Password, true).await?;
+    id.remember(session.to_string());
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Second step of password login when 2FA is enabled
+#[instrument(skip(id, reg, state, req))]
+#[post("/api/v1/account/login/totp")]
+async fn totp_login(
+    id: Identity,
+    reg: web::Json<AccLoginTotp>,
+    state: AppState,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    trace!("acc login via totp");
+    let reg = reg.into_inner();
+    let (user, device) = auth::verify_totp_challenge(&state.token_keys, &state.id, &reg.challenge)?;
+
+    let ip = peer_ip(&req);
     let mut conn = state.sql.acquire().await?;
-    let login_data = dao::user_by_email(&mut conn, &reg.email)
+    let res = limited(
+        &state.login_limiter,
+        ip.as_deref(),
+        &user.to_string(),
+        verify_second_factor(&mut conn, &user, &reg.code),
+    )
+    .await;
+    if let Err(AuthError::InvalidCredentials) = res {
+        AuditLog::new(&user, Event::LoginFailed)
+            .ip(ip.as_deref())
+            .detail("totp")
+            .write(&mut conn)
+            .await?;
+    }
+    res?;
+    let session =
+        dao::create_session(&mut conn, &user, device.as_deref(), LoginMethod::Password).await?;
+    audit_login(&mut conn, &user, ip.as_deref(), LoginMethod::Password, true).await?;
+    id.remember(session.to_string());
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Client IP for throttling. Uses the peer address, forwarding headers can be spoofed.
+fn peer_ip(req: &HttpRequest) -> Option<String> {
+    req.peer_addr().map(|v| v.ip().to_string())
+}
+
+/// Record login attempt of an existing user
+async fn audit_login(
+    sql: &mut DbConn,
+    user: &UserId,
+    ip: Option<&str>,
+    method: LoginMethod,
+    success: bool,
+) -> Result<()> {
+    let event = match success {
+        true => Event::Login,
+        false => Event::LoginFailed,
+    };
+    AuditLog::new(user, event)
+        .ip(ip)
+        .detail(method.as_ref())
+        .write(sql)
+        .await?;
+    Ok(())
+}
+
+/// Run a credential check under the login limiter, failed attempts are recorded for `ip` and `account`.
+/// The attempt stays reserved during verification.
+async fn limited<T>(
+    limiter: &LoginLimiter,
+    ip: Option<&str>,
+    account: &str,
+    verify: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let attempt = match limiter.check(ip, account) {
+        Ok(v) => v,
+        Err(wait) => {
+            debug!(?ip, "login throttled");
+            // round up, retrying early would only fail again
+            let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            return Err(AuthError::RateLimited(secs));
+        }
+    };
+    // other errors release the attempt on drop
+    match verify.await {
+        Err(AuthError::InvalidCredentials) => {
+            attempt.failure();
+            Err(AuthError::InvalidCredentials)
+        }
+        Err(e) => Err(e),
+        Ok(v) => {
+            attempt.success();
+            Ok(v)
+        }
+    }
+}
+
+/// Verify email + password, returns the user on success.
+/// Hashes with outdated parameters are upgraded.
+async fn verify_password_login(
+    sql: &mut DbConn,
+    email: &str,
+    password: String,
+    hashing: &PasswordHashing,
+    ip: Option<&str>,
+) -> Result<UserId> {
+    let login_data = dao::user_by_email(sql, email)
         .await?
         .ok_or(AuthError::InvalidCredentials)?;
+    let user = UserId(login_data.user_id);
     let hash_move = login_data.password;
-    task::spawn_blocking(move || -> Result<_> { verify_pw(reg.password, hash_move) })
+    let hashing = hashing.clone();
+    let res = task::spawn_blocking(move || -> Result<_> {
+        verify_pw(&password, &hash_move)?;
+        match needs_rehash(&hash_move, &hashing)? {
+            true => Ok(Some(hash_pw(password, &hashing)?)),
+            false => Ok(None),
+        }
+    })
+    .await
+    .context("failed joining verifier thread")?;
+    if let Err(AuthError::InvalidCredentials) = res {
+        audit_login(sql, &user, ip, LoginMethod::Password, false).await?;
+    }
+    let new_hash = res?;
+    if let Some(hash) = new_hash {
+        dao::update_password(sql, &user, &hash).await?;
+        trace!(%user, "upgraded password hash");
+    }
+    Ok(user)
+}
+
+async fn verify_pw_blocking(password: String, hash: String) -> Result<()> {
+    task::spawn_blocking(move || -> Result<_> { verify_pw(&password, &hash) })
         .await
-        .context("failed joining verifier thread")??;
-    // TODO: update last seen
-    id.remember(login_data.user_id.to_string());
+        .context("failed joining verifier thread")?
+}
+
+/// Verify the fresh proof for deleting the account, a stolen session alone isn't enough.
+/// Accounts without key and password can only be deleted shortly after logging in.
+pub(super) async fn verify_deletion(
+    sql: &mut DbConn,
+    limiter: &LoginLimiter,
+    server_id: &Uuid,
+    key_algorithms: &KeyAlgorithms,
+    user: &AuthUser,
+    reg: AccountDelete,
+    ip: Option<&str>,
+) -> Result<()> {
+    let AuthUser(user, session) = user;
+    let key_data = dao::user_key(sql, user).await?;
+    if let Some(proof) = reg.proof {
+        let key_data = key_data.ok_or(AuthError::InvalidProof("no key login"))?;
+        return verify_key_proof(
+            sql,
+            server_id,
+            key_algorithms,
+            user,
+            key_data,
+            "delete",
+            proof,
+        )
+        .await;
+    }
+    let login = dao::user_login(sql, user).await?;
+    if let Some(password) = reg.password {
+        let login = login.ok_or(AuthError::NoPasswordLogin)?;
+        return limited(
+            limiter,
+            ip,
+            &login.email,
+            verify_pw_blocking(password, login.password),
+        )
+        .await;
+    }
+    if key_data.is_some() || login.is_some() {
+        return Err(AuthError::InvalidProof("proof or password required"));
+    }
+    let fresh = dao::sessions(sql, user)
+        .await?
+        .into_iter()
+        .find(|v| v.uuid == *session)
+        .map_or(false, |v| {
+            Utc::now().naive_utc() - v.created < Duration::seconds(auth::FRESH_LOGIN_LIFETIME)
+        });
+    match fresh {
+        true => Ok(()),
+        false => Err(AuthError::InvalidProof("fresh login required")),
+    }
+}
+
+/// Verify TOTP or recovery code of user, consuming it
+async fn verify_second_factor(sql: &mut DbConn, user: &UserId, code: &str) -> Result<()> {
+    let user_totp = dao::totp(sql, user)
+        .await?
+        .filter(|v| v.confirmed)
+        .ok_or(AuthError::TotpNotEnrolled)?;
+    if totp::is_totp_code(code) {
+        let step = totp::verify(
+            &user_totp.secret,
+            code,
+            Utc::now().timestamp(),
+            user_totp.last_step,
+        )
+        .ok_or(AuthError::InvalidCredentials)?;
+        // guards against concurrent use of the same code
+        if !dao::totp_use_step(sql, user, step).await? {
+            return Err(AuthError::InvalidCredentials);
+        }
+    } else if !dao::use_recovery_code(sql, user, &totp::recovery_code_hash(code)).await? {
+        return Err(AuthError::InvalidCredentials);
+    }
+    Ok(())
+}
+
+/// Start TOTP enrollment for password logins, requires the password
+#[instrument(skip(reg, state, req))]
+#[post("/api/v1/account/totp/enroll")]
+async fn totp_enroll(
+    user: AuthUser,
+    reg: web::Json<TotpEnroll>,
+    state: AppState,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = user.0;
+    let reg = reg.into_inner();
+    let mut conn = state.sql.acquire().await?;
+    let login = dao::user_login(&mut conn, &user_id)
+        .await?
+        .ok_or(AuthError::NoPasswordLogin)?;
+    let ip = peer_ip(&req);
+    limited(
+        &state.login_limiter,
+        ip.as_deref(),
+        &login.email,
+        verify_pw_blocking(reg.password, login.password),
+    )
+    .await?;
+    let secret = totp::generate_secret();
+    dao::totp_enroll(&mut conn, &user_id, &secret).await?;
+    Ok(HttpResponse::Ok().json(TotpEnrollment {
+        secret: totp::encode_secret(&secret),
+        uri: totp::otpauth_uri(&secret, &login.email),
+    }))
+}
+
+/// Confirm TOTP enrollment with the first code, returns the recovery codes
+#[instrument(skip(reg, state))]
+#[post("/api/v1/account/totp/confirm")]
+async fn totp_confirm(
+    user: AuthUser,
+    reg: web::Json<TotpCode>,
+    state: AppState,
+) -> Result<HttpResponse> {
+    let user_id = user.0;
+    let mut conn = state.sql.acquire().await?;
+    let user_totp = dao::totp(&mut conn, &user_id)
+        .await?
+        .ok_or(AuthError::TotpNotEnrolled)?;
+    if user_totp.confirmed {
+        return Err(AuthError::TotpEnabled);
+    }
+    let step = totp::verify(&user_totp.secret, &reg.code, Utc::now().timestamp(), None)
+        .ok_or(AuthError::InvalidCredentials)?;
+    let (recovery_codes, hashes) = totp::generate_recovery_codes();
+    dao::totp_confirm(&mut conn, &user_id, step, &hashes).await?;
+    AuditLog::new(&user_id, Event::TotpEnabled)
+        .write(&mut conn)
+        .await?;
+    trace!(%user_id, "enabled totp");
+    Ok(HttpResponse::Ok().json(TotpRecoveryCodes { recovery_codes }))
+}
+
+/// Disable TOTP, requires the password and a TOTP or recovery code
+#[instrument(skip(reg, state, req))]
+#[post("/api/v1/account/totp/disable")]
+async fn totp_disable(
+    user: AuthUser,
+    reg: web::Json<TotpDisable>,
+    state: AppState,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = user.0;
+    let reg = reg.into_inner();
+    let mut conn = state.sql.acquire().await?;
+    let login = dao::user_login(&mut conn, &user_id)
+        .await?
+        .ok_or(AuthError::NoPasswordLogin)?;
+    let ip = peer_ip(&req);
+    let hash_move = login.password;
+    limited(&state.login_limiter, ip.as_deref(), &login.email, async {
+        verify_pw_blocking(reg.password, hash_move).await?;
+        verify_second_factor(&mut conn, &user_id, &reg.code).await
+    })
+    .await?;
+    dao::totp_disable(&mut conn, &user_id).await?;
+    AuditLog::new(&user_id, Event::TotpDisabled)
+        .ip(ip.as_deref())
+        .write(&mut conn)
+        .await?;
     Ok(HttpResponse::Ok().finish())
 }
 
-/// add email + password to account as login
+/// List all login sessions of the account
+#[instrument]
+#[get("/api/v1/account/sessions")]
+async fn account_sessions(user: AuthUser, state: AppState) -> Result<HttpResponse> {
+    let AuthUser(user_id, current) = user;
+    let mut sessions = dao::sessions(&mut *state.sql.acquire().await?, &user_id).await?;
+    for session in sessions.iter_mut() {
+        session.current = session.uuid == current;
+    }
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+/// Revoke a login session of the account
+#[instrument]
+#[delete("/api/v1/account/sessions/{session}")]
+async fn session_revoke(
+    user: AuthUser,
+    state: AppState,
+    path: web::Path<(Uuid,)>,
+) -> Result<HttpResponse> {
+    let user_id = user.0;
+    let (session,) = path.into_inner();
+    if !dao::revoke_session(&mut *state.sql.acquire().await?, &user_id, &session).await? {
+        return Err(AuthError::UnknownSession);
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Log out all other devices, revoking every session except the current one
+#[instrument]
+#[post("/api/v1/account/sessions/revoke-others")]
+async fn sessions_revoke_others(user: AuthUser, state: AppState) -> Result<HttpResponse> {
+    let AuthUser(user_id, current) = user;
+    dao::revoke_sessions(&mut *state.sql.acquire().await?, &user_id, Some(&current)).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Log out, revoking the current session
 #[instrument(skip(id))]
+#[post("/api/v1/account/logout")]
+async fn logout(id: Identity, user: AuthUser, state: AppState) -> Result<HttpResponse> {
+    let AuthUser(user_id, current) = user;
+    dao::revoke_session(&mut *state.sql.acquire().await?, &user_id, &current).await?;
+    id.forget();
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// add email + password to account as login
+#[instrument]
 #[post("/api/v1/account/register/password")]
 async fn app_password_register(
     reg: web::Json<PasswordBindRequest>,
-    id: Identity,
+    user: AuthUser,
     state: AppState,
 ) -> Result<HttpResponse> {
-    let user_id = get_user(id)?;
+    let user_id = user.0;
     trace!(?user_id, "acc info request");
     let reg = reg.into_inner();
 
     let pw_move = reg.password;
-    let hashed_password = task::spawn_blocking(move || -> Result<_> { hash_pw(pw_move) })
+    let hashing = state.config.password_hashing.clone();
+    let hashed_password = task::spawn_blocking(move || -> Result<_> { hash_pw(pw_move, &hashing) })
         .await
         .context("failed joining verifier thread")??;
 
-    dao::create_password_login(
-        &mut *state.sql.begin().await?,
-        &user_id,
-        &reg.email,
-        &hashed_password,
+    // existing logins have to be changed via change_password / change_email
+    let mut conn = state.sql.acquire().await?;
+    dao::create_password_login(&mut conn, &user_id, &reg.email, &hashed_password).await?;
+    AuditLog::new(&user_id, Event::PasswordAdded)
+        .write(&mut conn)
+        .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Start OIDC login, redirects to the provider.
+/// When already logged in, the external account gets linked to the current user.
+#[instrument(skip(user, state))]
+#[get("/api/v1/account/oidc/{provider}/login")]
+async fn oidc_login(
+    path: web::Path<(String,)>,
+    query: web::Query<OidcLogin>,
+    user: Option<AuthUser>,
+    state: AppState,
+) -> Result<HttpResponse> {
+    let (provider,) = path.into_inner();
+    let request = state.oidc.authorization_request(&provider).await?;
+    let link_user = user.map(|v| v.0);
+    dao::create_oidc_flow(
+        &mut *state.sql.acquire().await?,
+        &oidc::state_hash(&request.state),
+        &provider,
+        &request.nonce,
+        &request.verifier,
+        link_user.as_ref(),
+        query.device.as_deref(),
+        Utc::now().naive_utc() + Duration::minutes(oidc::FLOW_LIFETIME),
+    )
+    .await?;
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, request.url))
+        .cookie(oidc::state_cookie(
+            &state.cookie_key,
+            &request.state,
+            crate::SECURE_COOKIE,
+        ))
+        .finish())
+}
+
+/// OIDC redirect target, finishes the login
+#[instrument(skip(id, user, query, state, req))]
+#[get("/api/v1/account/oidc/{provider}/callback")]
+async fn oidc_callback(
+    id: Identity,
+    user: Option<AuthUser>,
+    path: web::Path<(String,)>,
+    query: web::Query<OidcCallback>,
+    state: AppState,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let (provider,) = path.into_inner();
+    let query = query.into_inner();
+    // flow has to be finished by the browser which started it
+    if !oidc::verify_state_cookie(
+        &state.cookie_key,
+        req.cookie(oidc::STATE_COOKIE),
+        &query.state,
+    ) {
+        debug!("oidc state cookie mismatch");
+        return Err(AuthError::InvalidCredentials);
+    }
+    let mut conn = state.sql.acquire().await?;
+    let flow = dao::take_oidc_flow(&mut conn, &oidc::state_hash(&query.state))
+        .await?
+        .ok_or(AuthError::InvalidCredentials)?;
+    if flow.provider != provider {
+        return Err(AuthError::InvalidCredentials);
+    }
+    let code = match (query.code, query.error) {
+        (Some(code), None) => code,
+        (_, error) => {
+            debug!(?error, "oidc authorization failed");
+            return Err(AuthError::InvalidCredentials);
+        }
+    };
+    let id_token = state
+        .oidc
+        .exchange_code(&provider, &code, &flow.verifier)
+        .await?;
+    let claims = state
+        .oidc
+        .validate_id_token(&provider, &id_token, &flow.nonce)
+        .await?;
+
+    let user = match flow.link_user {
+        Some(link_user) => {
+            // linking only for the user who started it
+            if user.map(|v| (v.0).0) != Some(link_user) {
+                debug!("oidc link user mismatch");
+                return Err(AuthError::InvalidCredentials);
+            }
+            let user = UserId(link_user);
+            dao::link_oidc(&mut conn, &user, &provider, &claims.sub).await?;
+            user
+        }
+        None => match dao::oidc_user(&mut conn, &provider, &claims.sub).await? {
+            Some(user) => user,
+            None => {
+                dao::register_oidc_user(&mut conn, &provider, &claims.sub, claims.display_name())
+                    .await?
+            }
+        },
+    };
+    let session =
+        dao::create_session(&mut conn, &user, flow.device.as_deref(), LoginMethod::Oidc).await?;
+    audit_login(
+        &mut conn,
+        &user,
+        peer_ip(&req).as_deref(),
+        LoginMethod::Oidc,
+        true,
+    )
+    .await?;
+    id.remember(session.to_string());
+    let mut removal = oidc::state_cookie(&state.cookie_key, "", crate::SECURE_COOKIE);
+    removal.make_removal();
+    Ok(HttpResponse::Ok().cookie(removal).finish())
+}
+
+/// Change password of the password login
+#[instrument(skip(reg, state, req))]
+#[post("/api/v1/account/password")]
+async fn change_password(
+    user: AuthUser,
+    reg: web::Json<PasswordChange>,
+    state: AppState,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let AuthUser(user_id, current) = user;
+    let reg = reg.into_inner();
+    let mut conn = state.sql.acquire().await?;
+    let login = dao::user_login(&mut conn, &user_id)
+        .await?
+        .ok_or(AuthError::NoPasswordLogin)?;
+    let ip = peer_ip(&req);
+    limited(
+        &state.login_limiter,
+        ip.as_deref(),
+        &login.email,
+        verify_pw_blocking(reg.password, login.password),
     )
     .await?;
-    // TODO: handle duplicate entries instead of erroring
 
+    let pw_move = reg.new_password;
+    let hashing = state.config.password_hashing.clone();
+    let hashed_password = task::spawn_blocking(move || -> Result<_> { hash_pw(pw_move, &hashing) })
+        .await
+        .context("failed joining verifier thread")??;
+    dao::update_password(&mut conn, &user_id, &hashed_password).await?;
+    if !reg.keep_sessions {
+        dao::revoke_sessions(&mut conn, &user_id, Some(&current)).await?;
+    }
+    AuditLog::new(&user_id, Event::PasswordChanged)
+        .ip(ip.as_deref())
+        .write(&mut conn)
+        .await?;
+    trace!(%user_id, "changed password");
     Ok(HttpResponse::Ok().finish())
 }
 
-pub(super) fn hash_pw(pw: String) -> Result<String> {
+/// Request email change, has to be confirmed via the token sent to the new email
+#[instrument(skip(reg, state, req))]
+#[post("/api/v1/account/email")]
+async fn change_email(
+    user: AuthUser,
+    reg: web::Json<EmailChange>,
+    state: AppState,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let AuthUser(user_id, current) = user;
+    let reg = reg.into_inner();
+    let mut conn = state.sql.acquire().await?;
+    let login = dao::user_login(&mut conn, &user_id)
+        .await?
+        .ok_or(AuthError::NoPasswordLogin)?;
+    let ip = peer_ip(&req);
+    limited(
+        &state.login_limiter,
+        ip.as_deref(),
+        &login.email,
+        verify_pw_blocking(reg.password, login.password),
+    )
+    .await?;
+
+    let keep = match reg.keep_sessions {
+        true => None,
+        false => Some(&current),
+    };
+    request_email_change(&mut conn, state.mailer.as_ref(), &user_id, &reg.email, keep).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Store a pending email change and mail its token to the new address.
+/// Other sessions than `revoke_except` are logged out if given.
+/// Nothing is stored if sending fails.
+pub(super) async fn request_email_change(
+    sql: &mut DbConn,
+    mailer: &dyn MailSender,
+    user: &UserId,
+    email: &str,
+    revoke_except: Option<&Uuid>,
+) -> Result<()> {
+    let mut transaction = sql.begin().await?;
+    let res = _request_email_change(&mut transaction, mailer, user, email, revoke_except).await;
+    if res.is_ok() {
+        transaction.commit().await?;
+    } else {
+        transaction.rollback().await?;
+    }
+    res
+}
+
+async fn _request_email_change(
+    transaction: &mut Transaction<'_, MySql>,
+    mailer: &dyn MailSender,
+    user: &UserId,
+    email: &str,
+    revoke_except: Option<&Uuid>,
+) -> Result<()> {
+    let (token, hash) = auth::generate_token();
+    dao::create_email_change(
+        &mut *transaction,
+        user,
+        email,
+        &hash,
+        auth::email_change_expiry(),
+    )
+    .await?;
+    if let Some(current) = revoke_except {
+        dao::revoke_sessions(&mut *transaction, user, Some(current)).await?;
+    }
+    mailer.email_change(email, &token).await?;
+    debug!(%user, "sent email change confirmation");
+    Ok(())
+}
+
+/// Confirm email change, the token is the proof of ownership
+#[instrument(skip(reg, state))]
+#[post("/api/v1/account/email/confirm")]
+async fn confirm_email(reg: web::Json<EmailConfirm>, state: AppState) -> Result<HttpResponse> {
+    let hash = auth::token_hash(&reg.token)?;
+    let mut conn = state.sql.acquire().await?;
+    let user = dao::confirm_email_change(&mut conn, &hash)
+        .await?
+        .ok_or(AuthError::InvalidCredentials)?;
+    AuditLog::new(&user, Event::EmailChanged)
+        .write(&mut conn)
+        .await?;
+    trace!(%user, "changed email");
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(super) fn hash_pw(pw: String, hashing: &PasswordHashing) -> Result<String> {
     let salt = argon2::password_hash::SaltString::generate(&mut OsRng);
 
-    // Argon2 with default params (Argon2id v19)
-    let argon2 = argon2::Argon2::default();
+    // Argon2id v19 with configured cost
+    let params = hashing
+        .params()
+        .context("invalid password hashing params")?;
+    let argon2 = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
 
     // Hash password to PHC string ($argon2id$v=19$...)
     Ok(argon2.hash_password(pw.as_bytes(), &salt)?.to_string())
 }
 
-pub(super) fn verify_pw(pw: String, hash: String) -> Result<()> {
-    let parsed_hash = argon2::PasswordHash::new(&hash)?;
+/// Whether the hash got created with another algorithm or weaker cost than configured
+pub(super) fn needs_rehash(hash: &str, hashing: &PasswordHashing) -> Result<bool> {
+    let parsed_hash = argon2::PasswordHash::new(hash)?;
+    if parsed_hash.algorithm != argon2::Algorithm::Argon2id.ident()
+        || parsed_hash.version != Some(argon2::Version::V0x13.into())
+    {
+        return Ok(true);
+    }
+    let params = argon2::Params::try_from(&parsed_hash)?;
+    Ok(params.m_cost() < hashing.memory
+        || params.t_cost() < hashing.iterations
+        || params.p_cost() < hashing.parallelism)
+}
+
+/// Verify password, the cost is taken from the hash
+pub(super) fn verify_pw(pw: &str, hash: &str) -> Result<()> {
+    let parsed_hash = argon2::PasswordHash::new(hash)?;
 
     let argon2 = argon2::Argon2::default();
 
-    argon2.verify_password(pw.as_bytes(), &parsed_hash)?;
+    argon2
+        .verify_password(pw.as_bytes(), &parsed_hash)
+        .map_err(|e| match e {
+            argon2::password_hash::Error::Password => AuthError::InvalidCredentials,
+            e => e.into(),
+        })?;
     Ok(())
 }
 
 /// App user info
 #[instrument(skip(id))]
 #[get("/api/v1/account/info")]
-async fn account_info(id: Identity, state: AppState, req: HttpRequest) -> Result<HttpResponse> {
-    let user_id = get_user(&id)?;
+async fn account_info(
+    id: Identity,
+    user: AuthUser,
+    state: AppState,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = user.0;
     Ok(
         match dao::user_by_uuid(&mut *state.sql.acquire().await?, &user_id).await? {
             Some(v) => HttpResponse::Ok().json(v),
@@ -237,9 +1047,63 @@ async fn account_info(id: Identity, state: AppState, req: HttpRequest) -> Result
     )
 }
 
-/// Retrieve user from IDentity or error out
-fn get_user<T: Borrow<actix_identity::Identity>>(id: T) -> Result<UserId> {
-    Ok(UserId(Uuid::parse_str(
-        &id.borrow().identity().ok_or(AuthError::NotAuthenticated)?,
-    )?))
+/// Change display name and automatic deletion
+#[instrument(skip(state))]
+#[patch("/api/v1/account")]
+async fn account_update(
+    user: AuthUser,
+    reg: web::Json<AccountUpdate>,
+    state: AppState,
+) -> Result<HttpResponse> {
+    let user_id = user.0;
+    let reg = reg.into_inner();
+    let name = reg.name.as_deref().map(str::trim);
+    if let Some(name) = name {
+        if name.is_empty() {
+            return Err(AuthError::InvalidInput("name empty"));
+        }
+        if name.chars().count() > dao::MAX_NAME_LENGTH {
+            return Err(AuthError::InvalidInput("name too long"));
+        }
+    }
+    let mut conn = state.sql.acquire().await?;
+    dao::update_user(&mut conn, &user_id, name, reg.delete_after).await?;
+    match dao::user_by_uuid(&mut conn, &user_id).await? {
+        Some(v) => Ok(HttpResponse::Ok().json(v)),
+        None => Err(AuthError::UnknownUser),
+    }
+}
+
+/// Download all data stored for the account
+#[instrument(skip(state))]
+#[get("/api/v1/account/export")]
+async fn account_export(user: AuthUser, state: AppState) -> Result<HttpResponse> {
+    let user_id = user.0;
+    trace!(%user_id, "account export");
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"account-export.json\"",
+        ))
+        .streaming(export::export_user(state.sql.clone(), user_id)))
+}
+
+/// Audit log of the account, newest first
+#[instrument(skip(state))]
+#[get("/api/v1/account/activity")]
+async fn account_activity(
+    user: AuthUser,
+    query: web::Query<ActivityQuery>,
+    state: AppState,
+) -> Result<HttpResponse> {
+    let user_id = user.0;
+    let activity = audit::activity(
+        &mut *state.sql.acquire().await?,
+        &user_id,
+        query.before,
+        query.limit,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(activity))
 }
@@ -0,0 +1,134 @@
+//! TOTP second factor (RFC 6238, SHA1, 6 digits, 30s steps) and recovery codes
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/// Length of a time step in seconds
+pub const STEP: i64 = 30;
+/// Digits per code
+pub const DIGITS: u32 = 6;
+/// Accepted steps before and after the current one, for clock drift
+const WINDOW: i64 = 1;
+/// Secret length in bytes, recommended size for SHA1
+const SECRET_LENGTH: usize = 20;
+/// Amount of recovery codes generated on enrollment
+pub const RECOVERY_CODES: usize = 10;
+/// Issuer shown in authenticator apps
+const ISSUER: &str = "VocableTrainer";
+
+/// Generate new random secret
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// Base32 representation of the secret, for manual entry
+pub fn encode_secret(secret: &[u8]) -> String {
+    BASE32_NOPAD.encode(secret)
+}
+
+/// otpauth URI for QR codes, see the Key Uri Format of Google Authenticator
+pub fn otpauth_uri(secret: &[u8], account: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = ISSUER,
+        account = url_encode(account),
+        secret = encode_secret(secret),
+        digits = DIGITS,
+        period = STEP
+    )
+}
+
+/// Code for the given time step
+pub fn code(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    value % 10u32.pow(DIGITS)
+}
+
+/// Time step of the given unix timestamp
+pub fn step_at(timestamp: i64) -> i64 {
+    timestamp / STEP
+}
+
+/// Verify code at the given unix timestamp, returns the matched time step.
+/// Steps up to `last_step` are rejected, codes are single use.
+pub fn verify(
+    secret: &[u8],
+    code_input: &str,
+    timestamp: i64,
+    last_step: Option<i64>,
+) -> Option<i64> {
+    if !is_totp_code(code_input) {
+        return None;
+    }
+    let code_input = code_input.trim();
+    let current = step_at(timestamp);
+    (current - WINDOW..=current + WINDOW)
+        .filter(|step| last_step.map_or(true, |last| *step > last))
+        .find(|step| {
+            let expected = format!("{:0width$}", code(secret, *step), width = DIGITS as usize);
+            bool::from(expected.as_bytes().ct_eq(code_input.as_bytes()))
+        })
+}
+
+/// Generate recovery codes, returns the codes and their hashes for storage
+pub fn generate_recovery_codes() -> (Vec<String>, Vec<Vec<u8>>) {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut raw = [0u8; 10];
+            OsRng.fill_bytes(&mut raw);
+            let encoded = BASE32_NOPAD.encode(&raw).to_lowercase();
+            // xxxx-xxxx-xxxx-xxxx for readability
+            let code = encoded
+                .as_bytes()
+                .chunks(4)
+                .map(|c| std::str::from_utf8(c).unwrap())
+                .collect::<Vec<_>>()
+                .join("-");
+            let hash = recovery_code_hash(&code);
+            (code, hash)
+        })
+        .unzip()
+}
+
+/// Hash of a recovery code, ignoring case and separators
+pub fn recovery_code_hash(code: &str) -> Vec<u8> {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    Sha256::digest(normalized.as_bytes()).to_vec()
+}
+
+/// Whether the input looks like a TOTP code instead of a recovery code
+pub fn is_totp_code(input: &str) -> bool {
+    let input = input.trim();
+    input.len() == DIGITS as usize && input.bytes().all(|c| c.is_ascii_digit())
+}
+
+fn url_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -1,7 +1,10 @@
 use std::fmt;
+use std::str::FromStr;
 
 use crate::prelude::*;
-use strum::EnumString;
+use jsonwebtoken::Algorithm;
+use sqlx::Row;
+use strum::{AsRefStr, EnumString};
 
 #[derive(Debug, sqlx::FromRow, Serialize)]
 pub struct User {
@@ -46,6 +49,19 @@ pub struct AccRegister {
 pub enum KeyType {
     RSA_PEM,
     EC_PEM,
+    ED_PEM,
+}
+
+impl KeyType {
+    /// Returns true if the JWT algorithm can be used with this key type
+    pub fn supports(&self, alg: Algorithm) -> bool {
+        use Algorithm::*;
+        match self {
+            KeyType::RSA_PEM => matches!(alg, RS256 | RS384 | RS512 | PS256 | PS384 | PS512),
+            KeyType::EC_PEM => matches!(alg, ES256 | ES384),
+            KeyType::ED_PEM => alg == EdDSA,
+        }
+    }
 }
 
 #[derive(Debug, sqlx::FromRow)]
@@ -60,16 +76,86 @@ pub struct RegisterClaims {
     pub delete_after: Option<u32>,
 }
 
+/// Claims required for all key signed proofs, replay protection
+#[derive(Debug, Deserialize)]
+pub struct ProofClaims<T> {
+    /// issued at, seconds since epoch
+    pub iat: i64,
+    /// expiration, seconds since epoch
+    pub exp: i64,
+    /// nonce, rejected on reuse
+    pub jti: String,
+    #[serde(flatten)]
+    pub claims: T,
+}
+
+/// How a session got created
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, AsRefStr, Serialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum LoginMethod {
+    Key,
+    Password,
+    Oidc,
+}
+
+/// Login session of a user
+#[derive(Debug, Serialize)]
+pub struct Session {
+    pub uuid: Uuid,
+    pub device: Option<String>,
+    pub created: Timestamp,
+    pub last_used: Timestamp,
+    pub method: LoginMethod,
+    /// Session of the current request
+    pub current: bool,
+}
+
+impl sqlx::FromRow<'_, sqlx::mysql::MySqlRow> for Session {
+    fn from_row(row: &sqlx::mysql::MySqlRow) -> sqlx::Result<Self> {
+        let method: String = row.try_get("method")?;
+        Ok(Session {
+            uuid: row.try_get("uuid")?,
+            device: row.try_get("device")?,
+            created: row.try_get("created")?,
+            last_used: row.try_get("last_used")?,
+            method: LoginMethod::from_str(&method).map_err(|e| sqlx::Error::Decode(e.into()))?,
+            current: false,
+        })
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AccLoginKey {
     pub iss: Uuid,
     pub proof: String,
+    /// device label for the session
+    #[serde(default)]
+    pub device: Option<String>,
+    /// return access + refresh token instead of setting the identity cookie
+    #[serde(default)]
+    pub token: bool,
+}
+
+#[derive(Deserialize)]
+pub struct TokenRefresh {
+    pub refresh_token: String,
+}
+
+// don't print tokens into the log
+impl fmt::Debug for TokenRefresh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenRefresh").finish()
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AccLoginPassword {
     pub email: String,
     pub password: String,
+    /// device label for the session
+    #[serde(default)]
+    pub device: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -106,3 +192,162 @@ pub struct UserLogin {
     pub password: String,
     pub verified: bool,
 }
+
+/// TOTP state of a user
+#[derive(Debug, sqlx::FromRow)]
+pub struct UserTotp {
+    pub secret: Vec<u8>,
+    pub confirmed: bool,
+    pub last_step: Option<i64>,
+}
+
+/// Started TOTP enrollment, has to be confirmed with a first code
+#[derive(Debug, Serialize)]
+pub struct TotpEnrollment {
+    /// base32 secret for manual entry
+    pub secret: String,
+    /// otpauth URI for QR codes
+    pub uri: String,
+}
+
+/// Single use recovery codes, only returned once
+#[derive(Serialize)]
+pub struct TotpRecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct TotpEnroll {
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct TotpDisable {
+    pub password: String,
+    /// TOTP or recovery code
+    pub code: String,
+}
+
+/// Returned by password login when a second factor is required
+#[derive(Serialize)]
+pub struct TotpChallenge {
+    pub totp_required: bool,
+    /// pass to the TOTP login step
+    pub challenge: String,
+    /// challenge lifetime in seconds
+    pub expires_in: i64,
+}
+
+/// Second step of password login with 2FA enabled
+#[derive(Deserialize)]
+pub struct AccLoginTotp {
+    pub challenge: String,
+    /// TOTP or recovery code
+    pub code: String,
+}
+
+/// Change password, requires the current one
+#[derive(Deserialize)]
+pub struct PasswordChange {
+    pub password: String,
+    pub new_password: String,
+    /// don't log out other sessions
+    #[serde(default)]
+    pub keep_sessions: bool,
+}
+
+/// Change email of the password login, requires the password
+#[derive(Deserialize)]
+pub struct EmailChange {
+    pub password: String,
+    pub email: String,
+    /// don't log out other sessions
+    #[serde(default)]
+    pub keep_sessions: bool,
+}
+
+#[derive(Deserialize)]
+pub struct EmailConfirm {
+    pub token: String,
+}
+
+/// Started OIDC login flow
+#[derive(Debug, sqlx::FromRow)]
+pub struct OidcFlow {
+    pub provider: String,
+    pub nonce: String,
+    pub verifier: String,
+    /// existing user to link the external account to
+    pub link_user: Option<Uuid>,
+    pub device: Option<String>,
+    pub expires: Timestamp,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcLogin {
+    /// device label for the session
+    #[serde(default)]
+    pub device: Option<String>,
+}
+
+/// Provider redirect after authorization
+#[derive(Deserialize)]
+pub struct OidcCallback {
+    pub state: String,
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Partial account update, absent fields are kept
+#[derive(Debug, Deserialize)]
+pub struct AccountUpdate {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// `null` removes the automatic deletion
+    #[serde(default, deserialize_with = "double_option")]
+    pub delete_after: Option<Option<u32>>,
+}
+
+/// Distinguish between a missing field and an explicit `null`
+fn double_option<'de, T, D>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Deserialize::deserialize(deserializer).map(Some)
+}
+
+/// Proof for deleting the account, key signed with `sub = "delete"` or the current password
+#[derive(Deserialize)]
+pub struct AccountDelete {
+    #[serde(default)]
+    pub proof: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// Account deletion got scheduled, restorable by logging in until `delete_at`
+#[derive(Debug, Serialize)]
+pub struct DeletionScheduled {
+    pub delete_at: Timestamp,
+}
+
+/// Paging of the audit log
+#[derive(Debug, Deserialize)]
+pub struct ActivityQuery {
+    /// continue after this entry id
+    pub before: Option<u64>,
+    #[serde(default = "ActivityQuery::default_limit")]
+    pub limit: u32,
+}
+
+impl ActivityQuery {
+    fn default_limit() -> u32 {
+        50
+    }
+}
@@ -95,6 +95,7 @@ def jwt_payload(server,user_id,sub) -> Dict[str, AnyStr]:
         "nbf": int( time()-4 ),
         "iat": int( time() ),
         "exp": int( time()+5 ),
+        "jti": str(uuid.uuid4()),
         "sub": sub,
         "iss": str(user_id),
         "name": "toaster",